@@ -0,0 +1,243 @@
+use oxc::{
+    ast::{
+        AstKind,
+        ast::{Argument, Expression, MemberExpression},
+    },
+    span::{GetSpan, Span},
+};
+
+use crate::{
+    cfg::{Config, ScriptKind},
+    diagnostic::Diagnostic,
+    pass::PassContext,
+    verify::is_global,
+};
+
+/// Objects that are, or lead straight to, the real window.
+const GLOBAL_OBJECTS: &[&str] = &["window", "self", "globalThis", "frames", "document"];
+
+/// A kind of escape the rewriter can't close off statically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EscapeCategory {
+    /// `window[name]` and the like, which may reach an unsafe global.
+    DynamicGlobalProperty,
+    /// `with`, which makes every name in its body dynamic.
+    With,
+    /// `Function` or `.constructor` called with code that isn't a literal.
+    DynamicFunction,
+    /// An assignment to `document.domain`.
+    DocumentDomain,
+    /// `arguments.callee.caller`, which reaches unrewritten callers.
+    CalleeCaller,
+    /// Walking `__proto__` or `getPrototypeOf` up the prototype chain.
+    ProtoWalk,
+    /// A name the proxy runtime uses.
+    RuntimeName,
+}
+
+impl EscapeCategory {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::DynamicGlobalProperty => "dynamic-global-property",
+            Self::With => "with",
+            Self::DynamicFunction => "dynamic-function",
+            Self::DocumentDomain => "document-domain",
+            Self::CalleeCaller => "callee-caller",
+            Self::ProtoWalk => "proto-walk",
+            Self::RuntimeName => "runtime-name",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::DynamicGlobalProperty => "computed property on a global object",
+            Self::With => "`with` statement",
+            Self::DynamicFunction => "`Function` built from a computed string",
+            Self::DocumentDomain => "write to `document.domain`",
+            Self::CalleeCaller => "`arguments.callee.caller`",
+            Self::ProtoWalk => "walk up the prototype chain",
+            Self::RuntimeName => "access to a proxy runtime name",
+        }
+    }
+}
+
+/// One place a script may get around the rewrite.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub category: EscapeCategory,
+    /// Span in the input.
+    pub span: Span,
+}
+
+/// What [`crate::Rewriter::audit`] found.
+#[derive(Debug)]
+pub struct Audit {
+    pub findings: Vec<Finding>,
+    /// Parse errors; findings only cover what the parser recovered.
+    pub errors: Vec<Diagnostic>,
+    pub kind: ScriptKind,
+}
+
+/// Finds the escapes in a parsed script, in source order.
+pub fn audit(cx: &PassContext<'_>) -> Vec<Finding> {
+    let nodes = cx.semantic.nodes();
+    let mut out = Vec::new();
+    for node in nodes.iter() {
+        let found = match node.kind() {
+            AstKind::ComputedMemberExpression(member)
+                if is_global_object(cx, &member.object) && !is_literal(&member.expression) =>
+            {
+                Some((EscapeCategory::DynamicGlobalProperty, member.span))
+            }
+            AstKind::WithStatement(with) => Some((EscapeCategory::With, with.span)),
+            AstKind::NewExpression(new)
+                if is_function_ctor(cx, &new.callee) && has_dynamic_code(&new.arguments) =>
+            {
+                Some((EscapeCategory::DynamicFunction, new.span))
+            }
+            AstKind::CallExpression(call)
+                if is_function_ctor(cx, &call.callee) && has_dynamic_code(&call.arguments) =>
+            {
+                Some((EscapeCategory::DynamicFunction, call.span))
+            }
+            AstKind::AssignmentExpression(assign)
+                if assign.left.as_member_expression().is_some_and(|m| {
+                    m.static_property_name() == Some("domain") && is_document(m.object())
+                }) =>
+            {
+                Some((EscapeCategory::DocumentDomain, assign.span))
+            }
+            AstKind::StaticMemberExpression(member)
+                if member.property.name == "caller" && is_arguments_callee(&member.object) =>
+            {
+                Some((EscapeCategory::CalleeCaller, member.span))
+            }
+            kind => proto_walk(kind, nodes.parent_kind(node.id()))
+                .or_else(|| runtime_name(cx.cfg, kind)),
+        };
+        if let Some((category, span)) = found {
+            out.push(Finding {
+                category,
+                span: cx.source_span(span),
+            });
+        }
+    }
+    out.sort_by_key(|f| (f.span.start, f.category));
+    out
+}
+
+fn is_global_object(cx: &PassContext<'_>, expr: &Expression<'_>) -> bool {
+    match expr.without_parentheses() {
+        Expression::Identifier(ident) => {
+            (GLOBAL_OBJECTS.contains(&ident.name.as_str()) || cx.cfg.is_unsafe_global(&ident.name))
+                && is_global(cx.semantic, ident.reference_id.get())
+        }
+        Expression::ThisExpression(_) => false,
+        expr => expr.as_member_expression().is_some_and(|m| {
+            m.static_property_name().is_some_and(|name| {
+                GLOBAL_OBJECTS.contains(&name) || cx.cfg.is_unsafe_property(name)
+            })
+        }),
+    }
+}
+
+fn is_literal(expr: &Expression<'_>) -> bool {
+    match expr.without_parentheses() {
+        Expression::StringLiteral(_) | Expression::NumericLiteral(_) => true,
+        Expression::TemplateLiteral(t) => t.expressions.is_empty(),
+        _ => false,
+    }
+}
+
+/// `Function`, or `x.constructor`, which is `Function` for any function.
+fn is_function_ctor(cx: &PassContext<'_>, callee: &Expression<'_>) -> bool {
+    match callee.without_parentheses() {
+        Expression::Identifier(ident) => {
+            ident.name == "Function" && is_global(cx.semantic, ident.reference_id.get())
+        }
+        expr => expr
+            .as_member_expression()
+            .is_some_and(|m| matches!(m.static_property_name(), Some("constructor" | "Function"))),
+    }
+}
+
+fn has_dynamic_code(args: &[Argument<'_>]) -> bool {
+    args.iter()
+        .any(|arg| arg.as_expression().is_none_or(|expr| !is_literal(expr)))
+}
+
+fn is_document(expr: &Expression<'_>) -> bool {
+    match expr.without_parentheses() {
+        Expression::Identifier(ident) => ident.name == "document",
+        expr => expr
+            .as_member_expression()
+            .is_some_and(|m| m.static_property_name() == Some("document")),
+    }
+}
+
+fn is_arguments_callee(expr: &Expression<'_>) -> bool {
+    expr.as_member_expression().is_some_and(|m| {
+        m.static_property_name() == Some("callee")
+            && matches!(m.object(), Expression::Identifier(ident) if ident.name == "arguments")
+    })
+}
+
+/// Flags the outermost step of a prototype walk at least two steps long.
+fn proto_walk(kind: AstKind<'_>, parent: AstKind<'_>) -> Option<(EscapeCategory, Span)> {
+    let inner = match kind {
+        AstKind::StaticMemberExpression(m) if m.property.name == "__proto__" => &m.object,
+        AstKind::ComputedMemberExpression(m)
+            if m.static_property_name().is_some_and(|n| n == "__proto__") =>
+        {
+            &m.object
+        }
+        AstKind::CallExpression(call) => proto_argument(&call.callee, &call.arguments)?,
+        _ => return None,
+    };
+    let continues = match parent {
+        AstKind::StaticMemberExpression(m) => m.property.name == "__proto__",
+        AstKind::ComputedMemberExpression(m) => {
+            m.static_property_name().is_some_and(|n| n == "__proto__")
+        }
+        AstKind::CallExpression(call) => proto_argument(&call.callee, &call.arguments).is_some(),
+        _ => false,
+    };
+    (!continues && proto_step(inner).is_some()).then(|| (EscapeCategory::ProtoWalk, kind.span()))
+}
+
+fn proto_step<'a>(expr: &'a Expression<'a>) -> Option<&'a Expression<'a>> {
+    match expr.without_parentheses() {
+        Expression::CallExpression(call) => proto_argument(&call.callee, &call.arguments),
+        expr => expr
+            .as_member_expression()
+            .filter(|m| m.static_property_name().is_some_and(|n| n == "__proto__"))
+            .map(MemberExpression::object),
+    }
+}
+
+/// The argument of `Object.getPrototypeOf(x)` or `Reflect.getPrototypeOf(x)`.
+fn proto_argument<'a>(
+    callee: &'a Expression<'a>,
+    args: &'a [Argument<'a>],
+) -> Option<&'a Expression<'a>> {
+    let member = callee.as_member_expression()?;
+    let is_get_prototype = member.static_property_name() == Some("getPrototypeOf")
+        && matches!(member.object(), Expression::Identifier(o) if o.name == "Object" || o.name == "Reflect");
+    if is_get_prototype {
+        args.first()?.as_expression()
+    } else {
+        None
+    }
+}
+
+fn runtime_name(cfg: &Config, kind: AstKind<'_>) -> Option<(EscapeCategory, Span)> {
+    let (name, span) = match kind {
+        AstKind::IdentifierReference(ident) => (ident.name.as_str(), ident.span),
+        AstKind::BindingIdentifier(ident) => (ident.name.as_str(), ident.span),
+        AstKind::IdentifierName(ident) => (ident.name.as_str(), ident.span),
+        AstKind::StringLiteral(lit) => (lit.value.as_str(), lit.span),
+        _ => return None,
+    };
+    cfg.is_runtime_name(name)
+        .then_some((EscapeCategory::RuntimeName, span))
+}
@@ -0,0 +1,125 @@
+use std::{cell::Cell, fmt, str::FromStr, time::Duration};
+
+use crate::stats::Clock;
+
+/// A limit a rewrite can run into, see `Flags::max_input_size`,
+/// `Flags::max_changes`, `Flags::time_budget` and `Flags::max_steps`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    InputSize,
+    Changes,
+    Time,
+    Steps,
+}
+
+impl Budget {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::InputSize => "input-size",
+            Self::Changes => "changes",
+            Self::Time => "time",
+            Self::Steps => "steps",
+        }
+    }
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What to do with a script that goes over a budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BudgetPolicy {
+    /// Return the source untouched.
+    #[default]
+    Passthrough,
+    /// Fail with `RewriteError::OverBudget`.
+    Reject,
+}
+
+impl FromStr for BudgetPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "passthrough" => Ok(Self::Passthrough),
+            "reject" => Ok(Self::Reject),
+            _ => Err(format!("unknown budget policy: {s}")),
+        }
+    }
+}
+
+/// How many steps go by between clock reads in [`Deadline::tick`].
+const STEPS_PER_CHECK: u32 = 1024;
+
+/// Tracks `Flags::time_budget` and `Flags::max_steps` through one rewrite.
+/// Once either is used up it stays that way, so loops can bail out and the
+/// caller can check afterwards.
+#[derive(Debug)]
+pub struct Deadline {
+    clock: Clock,
+    until: Option<Duration>,
+    max_steps: Option<u64>,
+    /// Steps since the clock was last read.
+    steps: Cell<u32>,
+    /// Steps in all.
+    taken: Cell<u64>,
+    exceeded: Cell<Option<Budget>>,
+}
+
+impl Deadline {
+    pub fn new(
+        clock: Clock,
+        start: Duration,
+        budget: Option<Duration>,
+        max_steps: Option<u64>,
+    ) -> Self {
+        Self {
+            clock,
+            until: budget.map(|b| start + b),
+            max_steps,
+            steps: Cell::new(0),
+            taken: Cell::new(0),
+            exceeded: Cell::new(None),
+        }
+    }
+
+    /// Reads the clock.
+    pub fn exceeded(&self) -> bool {
+        if self.exceeded.get().is_none()
+            && let Some(until) = self.until
+            && (self.clock)() > until
+        {
+            self.exceeded.set(Some(Budget::Time));
+        }
+        self.exceeded.get().is_some()
+    }
+
+    /// The budget that was used up, once [`Self::exceeded`] says so.
+    pub fn budget(&self) -> Budget {
+        self.exceeded.get().unwrap_or(Budget::Time)
+    }
+
+    /// For loops: counts a step, and only reads the clock every so often.
+    pub fn tick(&self) -> bool {
+        if let Some(max) = self.max_steps {
+            let taken = self.taken.get() + 1;
+            self.taken.set(taken);
+            if taken > max && self.exceeded.get().is_none() {
+                self.exceeded.set(Some(Budget::Steps));
+            }
+        }
+        if self.until.is_none() {
+            return self.exceeded.get().is_some();
+        }
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps % STEPS_PER_CHECK);
+        if steps == STEPS_PER_CHECK {
+            self.exceeded()
+        } else {
+            self.exceeded.get().is_some()
+        }
+    }
+}
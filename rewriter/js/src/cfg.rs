@@ -1,18 +1,78 @@
-use std::error::Error;
+use std::{error::Error, str::FromStr, time::Duration};
+
+use crate::budget::BudgetPolicy;
 
 pub type StringBuilder = String;
 
 pub trait UrlRewriter: Send + Sync {
+    /// Appends the proxied form of `url`, relative to `flags.base`, to
+    /// `builder`. `dest` is what the URL is loaded as, and `module` whether
+    /// it is loaded as a module.
     fn rewrite(
         &self,
         cfg: &Config,
         flags: &Flags,
         url: &str,
         builder: &mut StringBuilder,
+        dest: UrlDestination,
         module: bool,
     ) -> Result<(), Box<dyn Error + Sync + Send>>;
 }
 
+/// What a rewritten URL is loaded as, named after the fetch destinations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrlDestination {
+    /// `importScripts`.
+    Script,
+    Worker,
+    SharedWorker,
+    ServiceWorker,
+    PaintWorklet,
+    AudioWorklet,
+}
+
+impl UrlDestination {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Script => "script",
+            Self::Worker => "worker",
+            Self::SharedWorker => "sharedworker",
+            Self::ServiceWorker => "serviceworker",
+            Self::PaintWorklet => "paintworklet",
+            Self::AudioWorklet => "audioworklet",
+        }
+    }
+
+    /// Adds the query a proxied URL carries so the service worker can tell
+    /// how to rewrite what it fetches, which the request's own destination
+    /// doesn't always say: `dest` for anything but a script, and
+    /// `type=module` for modules. A classic script gets none. `builder` holds
+    /// the URL, whose own query the parameters are added to, ahead of any
+    /// fragment.
+    pub fn push_query(self, module: bool, builder: &mut StringBuilder) {
+        let mut query = String::new();
+        if self != Self::Script {
+            query.push_str("dest=");
+            query.push_str(self.as_str());
+        }
+        if module {
+            if !query.is_empty() {
+                query.push('&');
+            }
+            query.push_str("type=module");
+        }
+        if query.is_empty() {
+            return;
+        }
+        let end = builder.find('#').unwrap_or(builder.len());
+        let url = &builder[..end];
+        if !url.ends_with(['?', '&']) {
+            query.insert(0, if url.contains('?') { '&' } else { '?' });
+        }
+        builder.insert_str(end, &query);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub prefix: String,
@@ -28,8 +88,21 @@ pub struct Config {
     pub trysetfn: String,
     pub templocid: String,
     pub tempunusedid: String,
+    /// Identifiers wrapped with `wrapfn` wherever they refer to a global.
+    /// The client's `wrapfn` gives back the real value of any it has no
+    /// wrapper for.
+    pub unsafe_globals: Vec<String>,
+    /// Property names redirected to `wrappropertybase` on member access.
+    pub unsafe_properties: Vec<String>,
 }
 
+/// Kept in step with `DEFAULT_UNSAFE_NAMES` in src/shared/index.ts, which the
+/// browser config defaults to.
+pub const DEFAULT_UNSAFE_GLOBALS: &[&str] = &["location", "parent", "top", "eval"];
+/// The same names as [`DEFAULT_UNSAFE_GLOBALS`], reached as properties such
+/// as `window.top`.
+pub const DEFAULT_UNSAFE_PROPERTIES: &[&str] = DEFAULT_UNSAFE_GLOBALS;
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -46,6 +119,94 @@ impl Default for Config {
             trysetfn: "$webrascal$tryset".into(),
             templocid: "$webrascal$temploc".into(),
             tempunusedid: "$webrascal$tempunused".into(),
+            unsafe_globals: DEFAULT_UNSAFE_GLOBALS.iter().map(|&s| s.into()).collect(),
+            unsafe_properties: DEFAULT_UNSAFE_PROPERTIES
+                .iter()
+                .map(|&s| s.into())
+                .collect(),
+        }
+    }
+}
+
+impl Config {
+    pub fn is_unsafe_global(&self, name: &str) -> bool {
+        self.unsafe_globals.iter().any(|g| g == name)
+    }
+
+    pub fn is_unsafe_property(&self, name: &str) -> bool {
+        self.unsafe_properties.iter().any(|p| p == name)
+    }
+
+    /// Whether `name` is one the runtime defines for rewritten code.
+    pub fn is_runtime_name(&self, name: &str) -> bool {
+        name.starts_with(&self.wrappropertybase)
+            || [
+                &self.wrapfn,
+                &self.wrappropertyfn,
+                &self.cleanrestfn,
+                &self.importfn,
+                &self.rewritefn,
+                &self.setrealmfn,
+                &self.metafn,
+                &self.pushsourcemapfn,
+                &self.trysetfn,
+                &self.templocid,
+                &self.tempunusedid,
+            ]
+            .iter()
+            .any(|n| n.as_str() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScriptKind {
+    #[default]
+    Script,
+    Module,
+    /// Parse as a module when the source has module syntax: `import`,
+    /// `export`, `import.meta` or top-level `await`.
+    Auto,
+}
+
+impl ScriptKind {
+    pub fn is_module(self) -> bool {
+        self == Self::Module
+    }
+}
+
+impl From<bool> for ScriptKind {
+    fn from(module: bool) -> Self {
+        if module { Self::Module } else { Self::Script }
+    }
+}
+
+impl From<Option<bool>> for ScriptKind {
+    fn from(module: Option<bool>) -> Self {
+        module.map_or(Self::Auto, Self::from)
+    }
+}
+
+/// What to do with a script that has syntax errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InvalidJsPolicy {
+    /// Return the source untouched.
+    Passthrough,
+    /// Rewrite whatever the parser recovered.
+    #[default]
+    BestEffort,
+    /// Fail with `RewriteError::InvalidJs`.
+    Reject,
+}
+
+impl FromStr for InvalidJsPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "passthrough" => Ok(Self::Passthrough),
+            "best-effort" => Ok(Self::BestEffort),
+            "reject" => Ok(Self::Reject),
+            _ => Err(format!("unknown invalid js policy: {s}")),
         }
     }
 }
@@ -53,26 +214,82 @@ impl Default for Config {
 #[derive(Debug, Clone)]
 pub struct Flags {
     pub base: String,
+    /// URL the script was loaded from. Used to name the script in DevTools
+    /// and in diagnostics, left empty when unknown.
+    pub url: String,
+    /// Links the script to its sourcemap on the client. Derived from the
+    /// input when left empty, see [`crate::tag::source_tag`].
     pub sourcetag: String,
-    pub is_module: bool,
+    /// Mixed into a derived `sourcetag` when equal inputs need distinct tags.
+    pub tag_salt: String,
+    pub is_module: ScriptKind,
     pub capture_errors: bool,
     pub rascalitize: bool,
     pub do_sourcemaps: bool,
+    /// Register the sourcemap from the script itself through
+    /// `Config::pushsourcemapfn`. When unset the caller has to deliver
+    /// `RewriteResult::sourcemap` to the client some other way.
+    pub inline_sourcemaps: bool,
     pub strict_rewrites: bool,
     pub destructure_rewrites: bool,
+    pub invalid_js: InvalidJsPolicy,
+    /// Charset label from the `Content-Type` or `<script charset>`, used by
+    /// `Rewriter::rewrite_bytes` when the input has no BOM.
+    pub charset: Option<String>,
+    /// Re-parse the output and report anything the rewrite broke in
+    /// `RewriteResult::regressions`.
+    pub verify: bool,
+    /// Record where every rewrite came from in `RewriteResult::provenance`.
+    pub provenance: bool,
+    /// Also format the output for reading in `RewriteResult::pretty`, with a
+    /// source map back to the input. For debugging; it costs a second parse.
+    pub pretty: bool,
+    /// Skip parsing scripts that [`crate::prefilter::Prefilter`] proves have
+    /// nothing to rewrite.
+    pub prefilter: bool,
+    /// Don't rewrite inputs longer than this many bytes.
+    pub max_input_size: Option<usize>,
+    /// Don't rewrite scripts that need more rewrites than this.
+    pub max_changes: Option<usize>,
+    /// Give up on scripts that take longer than this to parse and rewrite,
+    /// measured with the rewriter's clock. It is checked between phases and
+    /// as the visitor goes; a parse can't be interrupted. On wasm32 the
+    /// rewriter needs a clock from `Rewriter::set_clock` for this, and fails
+    /// with `RewriteError::NoClock` without one.
+    pub time_budget: Option<Duration>,
+    /// Give up on scripts that take more than this many steps to visit,
+    /// counted as the visitor scans the source. Unlike `time_budget` it
+    /// needs no clock and gives the same answer on every run.
+    pub max_steps: Option<u64>,
+    /// What to do when a script goes over one of the budgets above.
+    pub over_budget: BudgetPolicy,
 }
 
 impl Default for Flags {
     fn default() -> Self {
         Self {
             base: "about:blank".into(),
-            sourcetag: "default".into(),
-            is_module: false,
+            url: String::new(),
+            sourcetag: String::new(),
+            tag_salt: String::new(),
+            is_module: ScriptKind::Script,
             capture_errors: false,
             rascalitize: false,
             do_sourcemaps: true,
+            inline_sourcemaps: true,
             strict_rewrites: true,
             destructure_rewrites: true,
+            invalid_js: InvalidJsPolicy::BestEffort,
+            charset: None,
+            verify: false,
+            provenance: false,
+            pretty: false,
+            prefilter: true,
+            max_input_size: None,
+            max_changes: None,
+            time_budget: None,
+            max_steps: None,
+            over_budget: BudgetPolicy::Passthrough,
         }
     }
-}
\ No newline at end of file
+}
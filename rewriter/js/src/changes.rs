@@ -1,62 +1,94 @@
 use std::cmp::Ordering;
 
 use oxc::span::Span;
-use smallvec::smallvec;
+use smallvec::SmallVec;
 use transform::{Transform, TransformElement, TransformLL, TransformType};
 
 use crate::cfg::Config;
 
+/// The text of a change in pieces, mostly borrowed from `Config` and `Flags`
+/// so that most changes are copied into the output without first building a
+/// string of their own.
+pub type Parts<'a> = SmallVec<[TransformElement<'a>; 4]>;
+
+/// Builds [`Parts`] from anything `TransformElement` converts from.
+macro_rules! parts {
+    ($($part:expr),* $(,)?) => {
+        $crate::changes::Parts::from_iter([$(::transform::TransformElement::from($part)),*])
+    };
+}
+pub(crate) use parts;
+
 #[derive(Debug, Clone)]
-pub enum JsChangeType {
-    InsertLeft(String),
-    InsertRight(String),
-    WrapFnRight(String),
-    RascalErrFn(String),
-    Replace(String),
+pub enum JsChangeType<'a> {
+    InsertLeft(Parts<'a>),
+    InsertRight(Parts<'a>),
+    WrapFnRight(Parts<'a>),
+    RascalErrFn(Parts<'a>),
+    Replace(Parts<'a>),
+    Inject(Parts<'a>),
 }
 
 #[derive(Debug, Clone)]
-pub struct JsChange {
+pub struct JsChange<'a> {
     pub span: Span,
-    pub ty: JsChangeType,
+    pub ty: JsChangeType<'a>,
 }
 
-impl JsChange {
-    pub fn insert_left_owned(span: Span, text: String) -> Self {
+impl<'a> JsChange<'a> {
+    pub fn insert_left(span: Span, text: Parts<'a>) -> Self {
         Self {
             span: Span::new(span.start, span.start),
             ty: JsChangeType::InsertLeft(text),
         }
     }
 
-    pub fn insert_right(span: Span, text: String) -> Self {
+    pub fn insert_right(span: Span, text: Parts<'a>) -> Self {
         Self {
             span: Span::new(span.end, span.end),
             ty: JsChangeType::WrapFnRight(text),
         }
     }
 
-    pub fn insert_right_owned(span: Span, text: String) -> Self {
+    pub fn insert_after(span: Span, text: Parts<'a>) -> Self {
         Self {
             span: Span::new(span.end, span.end),
             ty: JsChangeType::InsertRight(text),
         }
     }
 
-    pub fn rascal_err(span: Span, text: String) -> Self {
+    pub fn rascal_err(span: Span, text: Parts<'a>) -> Self {
         Self {
             span: Span::new(span.start, span.start),
             ty: JsChangeType::RascalErrFn(text),
         }
     }
 
-    pub fn replace_owned(span: Span, text: String) -> Self {
+    pub fn replace(span: Span, text: Parts<'a>) -> Self {
         Self {
             span,
             ty: JsChangeType::Replace(text),
         }
     }
 
+    pub fn inject(span: Span, text: Parts<'a>) -> Self {
+        Self {
+            span: Span::new(span.start, span.start),
+            ty: JsChangeType::Inject(text),
+        }
+    }
+
+    fn text(&self) -> &Parts<'a> {
+        match &self.ty {
+            JsChangeType::InsertLeft(text)
+            | JsChangeType::InsertRight(text)
+            | JsChangeType::WrapFnRight(text)
+            | JsChangeType::RascalErrFn(text)
+            | JsChangeType::Replace(text)
+            | JsChangeType::Inject(text) => text,
+        }
+    }
+
     fn priority(&self) -> u8 {
         match self.ty {
             JsChangeType::RascalErrFn(_) => 0,
@@ -66,15 +98,15 @@ impl JsChange {
     }
 }
 
-impl Eq for JsChange {}
+impl Eq for JsChange<'_> {}
 
-impl PartialEq for JsChange {
+impl PartialEq for JsChange<'_> {
     fn eq(&self, other: &Self) -> bool {
         self.span == other.span && self.priority() == other.priority()
     }
 }
 
-impl Ord for JsChange {
+impl Ord for JsChange<'_> {
     fn cmp(&self, other: &Self) -> Ordering {
         self.span
             .start
@@ -84,19 +116,27 @@ impl Ord for JsChange {
     }
 }
 
-impl PartialOrd for JsChange {
+impl PartialOrd for JsChange<'_> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<'a> Transform<'a> for JsChange {
+impl<'a> Transform<'a> for JsChange<'a> {
     type ToLowLevelData = Config;
 
     fn span(&self) -> Span {
         self.span
     }
 
+    fn output_len(&self, _data: &Self::ToLowLevelData) -> usize {
+        let text = self.text().iter().map(TransformElement::len).sum::<usize>();
+        match self.ty {
+            JsChangeType::Replace(_) => text,
+            _ => text + self.span.size() as usize,
+        }
+    }
+
     fn into_low_level(self, _data: &Self::ToLowLevelData, _offset: i32) -> TransformLL<'a> {
         match self.ty {
             JsChangeType::InsertLeft(text)
@@ -104,12 +144,16 @@ impl<'a> Transform<'a> for JsChange {
             | JsChangeType::WrapFnRight(text)
             | JsChangeType::RascalErrFn(text) => TransformLL {
                 ty: TransformType::Insert,
-                change: smallvec![TransformElement::Owned(text)],
+                change: text.into_iter().collect(),
             },
             JsChangeType::Replace(text) => TransformLL {
                 ty: TransformType::Replace,
-                change: smallvec![TransformElement::Owned(text)],
+                change: text.into_iter().collect(),
+            },
+            JsChangeType::Inject(text) => TransformLL {
+                ty: TransformType::Inject,
+                change: text.into_iter().collect(),
             },
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,37 @@
+use std::borrow::Cow;
+
+use encoding_rs::{Encoding, UTF_8};
+
+/// Script bytes decoded for the rewriter, see [`decode`].
+#[derive(Debug)]
+pub struct Decoded<'a> {
+    pub text: Cow<'a, str>,
+    /// The encoding the bytes were decoded as.
+    pub encoding: &'static Encoding,
+    /// Whether malformed sequences were replaced with U+FFFD.
+    pub malformed: bool,
+}
+
+/// Decodes a classic script the way browsers do: a BOM wins, then `label`
+/// (from the `Content-Type` or `<script charset>`), then UTF-8. Unknown labels
+/// are ignored rather than rejected.
+///
+/// A UTF-8 BOM is kept, since the rewrite already accounts for it; any other
+/// BOM is dropped with the encoding it names. Valid UTF-8 is borrowed.
+pub fn decode<'a>(bytes: &'a [u8], label: Option<&str>) -> Decoded<'a> {
+    let (encoding, bom) = Encoding::for_bom(bytes).unwrap_or_else(|| {
+        let labeled = label.and_then(|l| Encoding::for_label(l.as_bytes()));
+        (labeled.unwrap_or(UTF_8), 0)
+    });
+    let body = if encoding == UTF_8 {
+        bytes
+    } else {
+        &bytes[bom..]
+    };
+    let (text, malformed) = encoding.decode_without_bom_handling(body);
+    Decoded {
+        text,
+        encoding,
+        malformed,
+    }
+}
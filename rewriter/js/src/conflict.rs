@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+use oxc::span::Span;
+use smallvec::SmallVec;
+
+use crate::{
+    cfg::{Config, Flags},
+    changes::JsChangeType,
+    rewrite::Rewrite,
+};
+
+/// Where a rewrite changes the input.
+struct Footprint {
+    /// Extent of the rewrite's span and all its changes.
+    lo: u32,
+    hi: u32,
+    /// Each change's span, and whether it replaces what the span covers.
+    changes: SmallVec<[(Span, bool); 2]>,
+}
+
+impl Footprint {
+    fn new(rewrite: &Rewrite, cfg: &Config, flags: &Flags) -> Self {
+        let changes = rewrite
+            .clone()
+            .into_inner(cfg, flags)
+            .into_iter()
+            .map(|c| (c.span, matches!(c.ty, JsChangeType::Replace(_))))
+            .collect::<SmallVec<[(Span, bool); 2]>>();
+        let lo = changes
+            .iter()
+            .map(|(s, _)| s.start)
+            .fold(rewrite.span.start, u32::min);
+        let hi = changes
+            .iter()
+            .map(|(s, _)| s.end)
+            .fold(rewrite.span.end, u32::max);
+        Self { lo, hi, changes }
+    }
+
+    /// Whether applying both would lose part of one: their spans cross, or
+    /// one replaces text the other changes or inserts into.
+    fn conflicts(&self, other: &Self) -> bool {
+        let crosses = |a: &Self, b: &Self| a.lo < b.lo && b.lo < a.hi && a.hi < b.hi;
+        let swallows = |a: &Self, b: &Self| {
+            let mut replaced = a.changes.iter().filter(|(s, r)| *r && !s.is_empty());
+            replaced.any(|(r, _)| {
+                b.changes.iter().any(|(c, _)| {
+                    if c.is_empty() {
+                        r.start < c.start && c.start < r.end
+                    } else {
+                        c.start.max(r.start) < c.end.min(r.end)
+                    }
+                })
+            })
+        };
+        crosses(self, other)
+            || crosses(other, self)
+            || swallows(self, other)
+            || swallows(other, self)
+    }
+}
+
+/// Footprints by where they start, for finding those that may overlap a
+/// range.
+#[derive(Default)]
+struct Index {
+    starts: BTreeMap<u32, SmallVec<[usize; 1]>>,
+    /// Longest extent so far, which bounds how far back an overlap can start.
+    longest: u32,
+}
+
+impl Index {
+    fn insert(&mut self, i: usize, footprint: &Footprint) {
+        self.starts.entry(footprint.lo).or_default().push(i);
+        self.longest = self.longest.max(footprint.hi - footprint.lo);
+    }
+
+    fn near(&self, footprint: &Footprint) -> impl Iterator<Item = usize> + '_ {
+        self.starts
+            .range(footprint.lo.saturating_sub(self.longest)..=footprint.hi)
+            .flat_map(|(_, ids)| ids.iter().copied())
+    }
+}
+
+/// Settles overlaps between the built-in rewrites, `rewrites[..builtin]`,
+/// and those from passes and patch rules after them. The transformer only
+/// drops single changes, which would leave the rest of a rewrite such as the
+/// closing half of a wrap, so conflicts are settled a whole rewrite at a
+/// time: a pass or rule rewrite replaces the built-in rewrites it conflicts
+/// with, and is dropped if it conflicts with one from an earlier pass or
+/// rule. Gives whether to keep each rewrite.
+pub(crate) fn resolve(
+    rewrites: &[Rewrite],
+    builtin: usize,
+    cfg: &Config,
+    flags: &Flags,
+) -> Vec<bool> {
+    let mut keep = vec![true; rewrites.len()];
+    if builtin == rewrites.len() {
+        return keep;
+    }
+    let footprints = rewrites
+        .iter()
+        .map(|r| Footprint::new(r, cfg, flags))
+        .collect::<Vec<_>>();
+    let mut builtins = Index::default();
+    for (i, footprint) in footprints[..builtin].iter().enumerate() {
+        builtins.insert(i, footprint);
+    }
+    let mut added = Index::default();
+    for i in builtin..rewrites.len() {
+        let footprint = &footprints[i];
+        if added
+            .near(footprint)
+            .any(|j| footprint.conflicts(&footprints[j]))
+        {
+            keep[i] = false;
+            continue;
+        }
+        for j in builtins.near(footprint) {
+            if keep[j] && footprint.conflicts(&footprints[j]) {
+                keep[j] = false;
+            }
+        }
+        added.insert(i, footprint);
+    }
+    keep
+}
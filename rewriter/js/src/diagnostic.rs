@@ -0,0 +1,193 @@
+use oxc::{
+    diagnostics::{OxcDiagnostic, Severity as OxcSeverity},
+    span::Span,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Advice,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Advice => "advice",
+        }
+    }
+}
+
+/// A point in the input. `line` and `column` are 1-based, and columns count
+/// UTF-16 code units like browser stack traces do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Position {
+    pub offset: u32,
+    pub line: u32,
+    pub column: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub start: Position,
+    pub end: Position,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub severity: Severity,
+    /// Stable identifier, such as `TS(1005)` for parser errors with a code.
+    pub code: String,
+    /// `Flags::url` of the script the diagnostic is about.
+    pub url: String,
+    pub span: Option<Label>,
+    pub labels: Vec<Label>,
+    pub help: Option<String>,
+}
+
+/// Generic code for parser errors that oxc doesn't give one.
+pub const PARSE_ERROR: &str = "webrascal(parse)";
+/// The rewritten output has syntax errors the input didn't have.
+pub const VERIFY_SYNTAX: &str = "webrascal(verify-syntax)";
+/// The rewritten output still references an unsafe global directly.
+pub const VERIFY_LEAK: &str = "webrascal(verify-leak)";
+/// The script went over a budget and was not rewritten.
+pub const BUDGET: &str = "webrascal(budget)";
+/// The input had bytes its encoding can't decode.
+pub const CHARSET: &str = "webrascal(charset)";
+
+impl Diagnostic {
+    pub fn at(
+        severity: Severity,
+        code: &str,
+        message: String,
+        lines: &LineIndex,
+        span: Span,
+        url: &str,
+    ) -> Self {
+        Self {
+            message,
+            severity,
+            code: code.to_string(),
+            url: url.to_string(),
+            span: Some(Label {
+                start: lines.position(span.start),
+                end: lines.position(span.end),
+                message: None,
+            }),
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+
+    /// A diagnostic about the whole script.
+    pub fn whole(severity: Severity, code: &str, message: String, url: &str) -> Self {
+        Self {
+            message,
+            severity,
+            code: code.to_string(),
+            url: url.to_string(),
+            span: None,
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+
+    /// `base` is added to the spans oxc reports, for parses that didn't start
+    /// at the beginning of `lines`.
+    pub fn from_oxc(err: OxcDiagnostic, lines: &LineIndex, base: u32, url: &str) -> Self {
+        let severity = match err.severity {
+            OxcSeverity::Error => Severity::Error,
+            OxcSeverity::Warning => Severity::Warning,
+            OxcSeverity::Advice => Severity::Advice,
+        };
+        let code = if err.code.is_some() {
+            err.code.to_string()
+        } else {
+            PARSE_ERROR.to_string()
+        };
+
+        let all = err.labels.as_deref().unwrap_or_default();
+        let mut labels = all
+            .iter()
+            .map(|labelled| {
+                let start = base + labelled.offset() as u32;
+                Label {
+                    start: lines.position(start),
+                    end: lines.position(start + labelled.len() as u32),
+                    message: labelled.label().map(str::to_string),
+                }
+            })
+            .collect::<Vec<_>>();
+        let primary = all.iter().position(|l| l.primary()).unwrap_or(0);
+        let span = (!labels.is_empty()).then(|| labels.remove(primary));
+
+        Self {
+            message: err.message.to_string(),
+            severity,
+            code,
+            url: url.to_string(),
+            span,
+            labels,
+            help: err.help.as_ref().map(|h| h.to_string()),
+        }
+    }
+}
+
+/// Line starts of a source, for turning offsets into positions.
+#[derive(Debug, Clone)]
+pub struct LineIndex<'a> {
+    src: &'a str,
+    starts: Vec<u32>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(src: &'a str) -> Self {
+        let mut starts = vec![0];
+        let mut chars = src.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let next = match c {
+                '\r' if matches!(chars.peek(), Some((_, '\n'))) => continue,
+                '\n' | '\r' | '\u{2028}' | '\u{2029}' => i + c.len_utf8(),
+                _ => continue,
+            };
+            starts.push(next as u32);
+        }
+        Self { src, starts }
+    }
+
+    pub fn position(&self, offset: u32) -> Position {
+        let mut offset = offset.min(self.src.len() as u32);
+        while !self.src.is_char_boundary(offset as usize) {
+            offset -= 1;
+        }
+        let line = self.starts.partition_point(|&s| s <= offset) - 1;
+        let start = self.starts[line] as usize;
+        let column = self.src[start..offset as usize].encode_utf16().count();
+        Position {
+            offset,
+            line: line as u32 + 1,
+            column: column as u32 + 1,
+        }
+    }
+
+    /// Offsets where each line starts.
+    pub fn starts(&self) -> &[u32] {
+        &self.starts
+    }
+
+    /// Text of a 1-based line, without its terminator.
+    pub fn line(&self, line: u32) -> &'a str {
+        let idx = (line as usize).saturating_sub(1).min(self.starts.len() - 1);
+        let start = self.starts[idx] as usize;
+        let end = self
+            .starts
+            .get(idx + 1)
+            .map_or(self.src.len(), |&e| e as usize);
+        self.src[start..end].trim_end_matches(['\n', '\r', '\u{2028}', '\u{2029}'])
+    }
+}
@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+use crate::{budget::Budget, diagnostic::Diagnostic};
+
+#[derive(Debug, Error)]
+pub enum RewriteError {
+    /// The input didn't parse and `Flags::invalid_js` is
+    /// `InvalidJsPolicy::Reject`.
+    #[error("invalid js: {}", .errors.first().map_or("", |e| e.message.as_str()))]
+    InvalidJs { errors: Vec<Diagnostic> },
+    /// The rewrite went over a budget and `Flags::over_budget` is
+    /// `BudgetPolicy::Reject`.
+    #[error("rewrite went over its {budget} budget")]
+    OverBudget { budget: Budget },
+    /// `Flags::time_budget` was set on a target without a default clock and
+    /// none was set with `Rewriter::set_clock`.
+    #[error("time budget needs a clock, see Rewriter::set_clock")]
+    NoClock,
+}
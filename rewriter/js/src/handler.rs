@@ -0,0 +1,102 @@
+use oxc::{
+    ast::ast::{Expression, FunctionBody, Program, Statement},
+    span::Span,
+};
+
+use crate::{
+    RewriteOutcome,
+    diagnostic::{self, Diagnostic, Label, LineIndex, Severity},
+    rewrite::{Rewrite, RewriteType},
+};
+
+/// What a handler body is parsed inside: the function an event handler
+/// attribute compiles to, with the scope chain it runs in. The comment keeps
+/// the visitor's look-behind for parameter lists from reaching `function`.
+pub const HEAD: &str = "with(document)with(form)with(element)(function(event){/* handler body */";
+/// Starts on its own line so a trailing line comment can't swallow it.
+pub const TAIL: &str = "\n})";
+
+/// What [`crate::Rewriter::rewrite_handler`] made of a handler body.
+#[derive(Debug)]
+pub struct HandlerResult {
+    /// The rewritten body, without the wrapper it was parsed in.
+    pub js: Vec<u8>,
+    /// Binary map from `js` to the body.
+    pub sourcemap: Vec<u8>,
+    /// Positions are in the body.
+    pub errors: Vec<Diagnostic>,
+    pub outcome: RewriteOutcome,
+}
+
+/// `body` inside [`HEAD`] and [`TAIL`].
+pub fn wrap(body: &str) -> String {
+    let mut out = String::with_capacity(HEAD.len() + body.len() + TAIL.len());
+    out.push_str(HEAD);
+    out.push_str(body);
+    out.push_str(TAIL);
+    out
+}
+
+/// Whether `program`, parsed from `wrap(body)`, is the wrapper function with
+/// the whole body inside it. A body like `}); x; ((function(){` parses but
+/// closes the function early.
+pub fn contains_body(program: &Program<'_>, body: &str) -> bool {
+    let open = HEAD.find('{').unwrap_or_default();
+    let expected = Span::new(
+        open as u32,
+        (HEAD.len() + body.len() + TAIL.len()) as u32 - 1,
+    );
+    program.body.len() == 1 && function_body(&program.body[0]).is_some_and(|f| f.span == expected)
+}
+
+fn function_body<'a>(stmt: &'a Statement<'a>) -> Option<&'a FunctionBody<'a>> {
+    match stmt {
+        Statement::WithStatement(with) => function_body(&with.body),
+        Statement::ExpressionStatement(expr) => match expr.expression.without_parentheses() {
+            Expression::FunctionExpression(f) => f.body.as_deref(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The diagnostic for a body that [`contains_body`] rejected.
+pub fn escaped(url: &str) -> Diagnostic {
+    Diagnostic::whole(
+        Severity::Error,
+        diagnostic::PARSE_ERROR,
+        "handler body closes the function it is compiled into".to_string(),
+        url,
+    )
+}
+
+/// Moves `diag`, made against the wrapped body, onto `lines` of the body.
+pub fn unwrap_diagnostic(mut diag: Diagnostic, lines: &LineIndex) -> Diagnostic {
+    let unwrap = |label: &mut Label| {
+        let head = HEAD.len() as u32;
+        label.start = lines.position(label.start.offset.saturating_sub(head));
+        label.end = lines.position(label.end.offset.saturating_sub(head));
+    };
+    diag.span.iter_mut().for_each(unwrap);
+    diag.labels.iter_mut().for_each(unwrap);
+    diag
+}
+
+/// Keeps the rewrites inside the body, moved to body offsets. The rest are
+/// the source tag, `sourceURL` and anything else touching the wrapper.
+pub fn unwrap_rewrites(rewrites: Vec<Rewrite>, body: &str) -> Vec<Rewrite> {
+    let head = HEAD.len() as u32;
+    let end = head + body.len() as u32;
+    let unwrap = |span: Span| Span::new(span.start - head, span.end - head);
+    rewrites
+        .into_iter()
+        .filter(|r| r.span.start >= head && r.span.end <= end)
+        .map(|mut r| {
+            r.span = unwrap(r.span);
+            if let RewriteType::Eval { inner } = &mut r.ty {
+                *inner = unwrap(*inner);
+            }
+            r
+        })
+        .collect()
+}
@@ -0,0 +1,85 @@
+use oxc::span::Span;
+use serde::Deserialize;
+
+use crate::{
+    cfg::{Flags, ScriptKind},
+    injection::InjectionPoints,
+    patch::url_matches,
+    rewrite::{Rewrite, RewriteType},
+};
+
+/// Code run at the start of matching scripts, loaded from JSON:
+///
+/// ```json
+/// { "name": "raf-polyfill", "host": "*.example.com", "kind": "script", "code": "..." }
+/// ```
+///
+/// The code is inserted as is, without being rewritten, and recorded in the
+/// map as injected so it doesn't pass for part of the script.
+///
+/// In a module the code runs first among the module's own statements, but
+/// only after every module it imports has been evaluated, as imports are
+/// hoisted. Code that has to run before those, such as a polyfill they rely
+/// on, belongs in a classic script ahead of the module graph instead.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InitScript {
+    /// Reported in `RewriteResult::injected_scripts`.
+    pub name: String,
+    /// Glob over the script URL, `*` matching anything.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Glob over the host of the script URL.
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub kind: InitKind,
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InitKind {
+    #[default]
+    Any,
+    Script,
+    Module,
+}
+
+pub fn parse_init_scripts(json: &str) -> serde_json::Result<Vec<InitScript>> {
+    serde_json::from_str(json)
+}
+
+impl InitScript {
+    pub fn matches(&self, url: &str, kind: ScriptKind) -> bool {
+        let kind = match self.kind {
+            InitKind::Any => true,
+            InitKind::Script => kind == ScriptKind::Script,
+            InitKind::Module => kind == ScriptKind::Module,
+        };
+        kind && url_matches(self.url.as_deref(), self.host.as_deref(), url)
+    }
+
+    /// Inserts the code after the hashbang and directive prologue, so it
+    /// runs before the first statement under the script's own strictness.
+    /// It goes in a block so its `let`, `const` and `class` declarations
+    /// can't collide with the script's, which would stop a module from
+    /// loading at all.
+    pub fn rewrite(&self, points: InjectionPoints, flags: &Flags) -> Rewrite {
+        let mut text = String::new();
+        // The source tag normally supplies the line break after a hashbang
+        // that runs to the end of the input.
+        if points.comment_newline && !flags.do_sourcemaps {
+            text.push('\n');
+        }
+        if points.code_semicolon {
+            text.push(';');
+        }
+        text.push('{');
+        text.push_str(&self.code);
+        text.push_str("\n}");
+        Rewrite {
+            span: Span::new(points.code, points.code),
+            ty: RewriteType::Inject { text },
+        }
+    }
+}
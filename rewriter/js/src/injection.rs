@@ -0,0 +1,127 @@
+use oxc::ast::ast::Program;
+
+const BOM: &str = "\u{feff}";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InjectionPoints {
+    /// Length of a leading byte order mark, which is never parsed or rewritten.
+    pub bom: u32,
+    /// Where comments can be inserted: after the BOM and the hashbang line.
+    pub comment: u32,
+    /// Where statements can be inserted: after the directive prologue.
+    pub code: u32,
+    /// The hashbang runs to the end of the input, so a line break has to be
+    /// inserted before anything at `comment`.
+    pub comment_newline: bool,
+    /// The last directive was terminated by ASI, so code inserted at `code`
+    /// has to start with a `;`.
+    pub code_semicolon: bool,
+}
+
+impl InjectionPoints {
+    /// `program` must have been parsed from `js` with the BOM stripped,
+    /// see [`strip_bom`].
+    pub fn new(js: &str, program: &Program<'_>) -> Self {
+        let bom = bom_len(js);
+        let mut comment = bom;
+        let mut comment_newline = false;
+
+        if let Some(hashbang) = &program.hashbang {
+            let end = (bom + hashbang.span.end) as usize;
+            let eol = line_terminator_len(&js[end..]);
+            comment = (end + eol) as u32;
+            comment_newline = eol == 0;
+        }
+
+        let mut code = comment;
+        let mut code_semicolon = false;
+        if let Some(last) = program.directives.last() {
+            code = code.max(bom + last.span.end);
+            code_semicolon = !js[..code as usize].ends_with(';');
+        }
+
+        Self {
+            bom,
+            comment,
+            code,
+            comment_newline,
+            code_semicolon,
+        }
+    }
+
+    /// Finds the injection points without a parse, for scripts that are not
+    /// otherwise parsed. Gives up when the script could start with a
+    /// directive, which would take a parser to delimit.
+    pub fn lexical(js: &str) -> Option<Self> {
+        let bom = bom_len(js);
+        let mut comment = bom;
+        let mut comment_newline = false;
+
+        let body = strip_bom(js);
+        if body.starts_with("#!") {
+            let end = body
+                .find(['\n', '\r', '\u{2028}', '\u{2029}'])
+                .unwrap_or(body.len());
+            let end = bom as usize + end;
+            let eol = line_terminator_len(&js[end..]);
+            comment = (end + eol) as u32;
+            comment_newline = eol == 0;
+        }
+
+        // Anything unusual, such as whitespace only JS knows about, counts too.
+        let first = first_token(&js[comment as usize..]);
+        if !first.is_none_or(|c| c.is_ascii() && c != '"' && c != '\'') {
+            return None;
+        }
+
+        Some(Self {
+            bom,
+            comment,
+            code: comment,
+            comment_newline,
+            code_semicolon: false,
+        })
+    }
+}
+
+/// First character of the first token, skipping whitespace and comments.
+fn first_token(mut rest: &str) -> Option<char> {
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("//") {
+            rest = after
+                .find(['\n', '\r', '\u{2028}', '\u{2029}'])
+                .map_or("", |i| &after[i..]);
+        } else if let Some(after) = rest.strip_prefix("/*") {
+            rest = after.find("*/").map_or("", |i| &after[i + 2..]);
+        } else {
+            return rest.chars().next();
+        }
+    }
+}
+
+/// Splits off a leading BOM. Browsers drop it while decoding, and a hashbang
+/// is only valid at the very start of what the parser sees.
+pub fn strip_bom(js: &str) -> &str {
+    js.strip_prefix(BOM).unwrap_or(js)
+}
+
+fn bom_len(js: &str) -> u32 {
+    if js.starts_with(BOM) {
+        BOM.len() as u32
+    } else {
+        0
+    }
+}
+
+fn line_terminator_len(rest: &str) -> usize {
+    if rest.starts_with("\r\n") {
+        2
+    } else if let Some(c) = rest.chars().next()
+        && matches!(c, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+    {
+        c.len_utf8()
+    } else {
+        0
+    }
+}
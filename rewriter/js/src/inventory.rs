@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use oxc::{
+    ast::{
+        AstKind,
+        ast::{Argument, Expression},
+    },
+    span::Span,
+};
+
+use crate::{cfg::ScriptKind, diagnostic::Diagnostic, pass::PassContext, verify::is_global};
+
+/// A browser API that a site may need the proxy to support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    WebSocket,
+    Worker,
+    SharedWorker,
+    ServiceWorker,
+    ImportScripts,
+    Eval,
+    Function,
+    SharedArrayBuffer,
+    WebAssembly,
+    DocumentWrite,
+    SyncXhr,
+    DynamicImport,
+    WebRtc,
+}
+
+impl Capability {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::WebSocket => "websocket",
+            Self::Worker => "worker",
+            Self::SharedWorker => "shared-worker",
+            Self::ServiceWorker => "service-worker",
+            Self::ImportScripts => "import-scripts",
+            Self::Eval => "eval",
+            Self::Function => "function",
+            Self::SharedArrayBuffer => "shared-array-buffer",
+            Self::WebAssembly => "webassembly",
+            Self::DocumentWrite => "document-write",
+            Self::SyncXhr => "sync-xhr",
+            Self::DynamicImport => "dynamic-import",
+            Self::WebRtc => "webrtc",
+        }
+    }
+}
+
+/// One use of a capability.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Usage {
+    pub capability: Capability,
+    /// Span in the input.
+    pub span: Span,
+}
+
+/// What [`crate::Rewriter::inventory`] found.
+#[derive(Debug)]
+pub struct Inventory {
+    pub uses: Vec<Usage>,
+    /// Parse errors; uses only cover what the parser recovered.
+    pub errors: Vec<Diagnostic>,
+    pub kind: ScriptKind,
+}
+
+impl Inventory {
+    /// How often each capability is used.
+    pub fn counts(&self) -> BTreeMap<Capability, usize> {
+        let mut counts = BTreeMap::new();
+        for usage in &self.uses {
+            *counts.entry(usage.capability).or_default() += 1;
+        }
+        counts
+    }
+}
+
+/// Lists the capabilities a parsed script uses, in source order.
+pub fn inventory(cx: &PassContext<'_>) -> Vec<Usage> {
+    let mut out = Vec::new();
+    for node in cx.semantic.nodes().iter() {
+        let found = match node.kind() {
+            AstKind::NewExpression(new) => constructed(cx, &new.callee).map(|c| (c, new.span)),
+            AstKind::CallExpression(call) => {
+                called(cx, &call.callee, &call.arguments).map(|c| (c, call.span))
+            }
+            AstKind::ImportExpression(import) => Some((Capability::DynamicImport, import.span)),
+            AstKind::IdentifierReference(ident)
+                if matches!(ident.name.as_str(), "SharedArrayBuffer" | "WebAssembly")
+                    && is_global(cx.semantic, ident.reference_id.get()) =>
+            {
+                let capability = if ident.name == "WebAssembly" {
+                    Capability::WebAssembly
+                } else {
+                    Capability::SharedArrayBuffer
+                };
+                Some((capability, ident.span))
+            }
+            _ => None,
+        };
+        if let Some((capability, span)) = found {
+            out.push(Usage {
+                capability,
+                span: cx.source_span(span),
+            });
+        }
+    }
+    out.sort_by_key(|u| (u.span.start, u.capability));
+    out
+}
+
+/// The name a callee refers to: a global, or a property of anything.
+fn callee_name<'a>(cx: &PassContext<'_>, callee: &'a Expression<'a>) -> Option<&'a str> {
+    match callee.without_parentheses() {
+        Expression::Identifier(ident) if is_global(cx.semantic, ident.reference_id.get()) => {
+            Some(ident.name.as_str())
+        }
+        expr => expr.as_member_expression()?.static_property_name(),
+    }
+}
+
+fn constructed(cx: &PassContext<'_>, callee: &Expression<'_>) -> Option<Capability> {
+    Some(match callee_name(cx, callee)? {
+        "WebSocket" => Capability::WebSocket,
+        "Worker" => Capability::Worker,
+        "SharedWorker" => Capability::SharedWorker,
+        "Function" => Capability::Function,
+        "RTCPeerConnection" | "webkitRTCPeerConnection" => Capability::WebRtc,
+        _ => return None,
+    })
+}
+
+fn called(
+    cx: &PassContext<'_>,
+    callee: &Expression<'_>,
+    args: &[Argument<'_>],
+) -> Option<Capability> {
+    let member = callee.without_parentheses().as_member_expression();
+    let object = member.map(|m| m.object().without_parentheses());
+    let object_name = object.and_then(|o| match o {
+        Expression::Identifier(ident) => Some(ident.name.as_str()),
+        o => o.as_member_expression()?.static_property_name(),
+    });
+    Some(match (callee_name(cx, callee)?, object_name) {
+        ("eval", None) => Capability::Eval,
+        ("Function", None) => Capability::Function,
+        ("importScripts", None) => Capability::ImportScripts,
+        ("register", Some("serviceWorker")) => Capability::ServiceWorker,
+        ("write" | "writeln", Some("document")) => Capability::DocumentWrite,
+        ("open", Some(_)) if is_sync_open(args) => Capability::SyncXhr,
+        _ => return None,
+    })
+}
+
+/// `xhr.open(method, url, false)`.
+fn is_sync_open(args: &[Argument<'_>]) -> bool {
+    matches!(
+        args.get(2).and_then(Argument::as_expression),
+        Some(Expression::BooleanLiteral(b)) if !b.value
+    )
+}
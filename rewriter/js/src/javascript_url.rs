@@ -0,0 +1,45 @@
+use std::borrow::Cow;
+
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, utf8_percent_encode};
+
+pub const SCHEME: &str = "javascript:";
+
+/// Encoded on the way out: `%` so decoding gives back exactly the script,
+/// tabs and newlines which the URL parser would drop, and what could end the
+/// URL wherever it gets written.
+const ENCODE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'%')
+    .add(b'\'')
+    .add(b'<')
+    .add(b'>')
+    .add(b'`');
+
+/// The script a browser runs for `url`, or `None` when it isn't a
+/// `javascript:` URL. Like the URL parser, leading and trailing C0 controls
+/// and spaces are trimmed, tabs and newlines anywhere are dropped, and the
+/// scheme is matched without case. The rest, query and fragment included, is
+/// percent-decoded and decoded as UTF-8.
+pub fn script(url: &str) -> Option<String> {
+    let url = url.trim_matches(|c: char| c <= ' ');
+    let url: Cow<str> = if url.contains(['\t', '\n', '\r']) {
+        url.replace(['\t', '\n', '\r'], "").into()
+    } else {
+        url.into()
+    };
+    let scheme = url.get(..SCHEME.len())?;
+    if !scheme.eq_ignore_ascii_case(SCHEME) {
+        return None;
+    }
+    let source = percent_decode_str(&url[SCHEME.len()..]).decode_utf8_lossy();
+    Some(source.into_owned())
+}
+
+/// A `javascript:` URL that runs `script`.
+pub fn encode(script: &str) -> String {
+    let mut out = String::with_capacity(SCHEME.len() + script.len());
+    out.push_str(SCHEME);
+    out.extend(utf8_percent_encode(script, ENCODE));
+    out
+}
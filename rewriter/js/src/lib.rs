@@ -1,34 +1,226 @@
+use std::{
+    ops::Deref,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
 use anyhow::{Result, anyhow};
-use oxc::{allocator::Allocator, parser::Parser, span::SourceType};
-use transform::Transformer;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use encoding_rs::UTF_8;
+use oxc::{
+    allocator::Allocator,
+    parser::{Parser, ParserReturn},
+    semantic::SemanticBuilder,
+    span::SourceType,
+};
+use transform::{INSERT_RECORD_LEN, TransformOutput, Transformer, encode_map, encoded_map_len};
 
+pub mod audit;
+pub mod budget;
 pub mod cfg;
 pub mod changes;
+pub mod charset;
+mod conflict;
+pub mod diagnostic;
+pub mod error;
+pub mod handler;
+pub mod init;
+pub mod injection;
+pub mod inventory;
+pub mod javascript_url;
+pub mod pass;
+pub mod patch;
+pub mod prefilter;
+pub mod pretty;
 pub mod rewrite;
+pub mod stats;
+pub mod tag;
+pub mod verify;
 pub mod visitor;
 
-use cfg::{Config, Flags, UrlRewriter};
+use audit::Audit;
+use budget::{Budget, BudgetPolicy, Deadline};
+use cfg::{Config, Flags, InvalidJsPolicy, ScriptKind, UrlRewriter};
 use changes::JsChange;
+use diagnostic::{Diagnostic, LineIndex, Severity};
+use error::RewriteError;
+use handler::HandlerResult;
+use init::InitScript;
+use injection::{InjectionPoints, strip_bom};
+use inventory::Inventory;
+use pass::{PassContext, RewritePass};
+use patch::PatchRule;
+use prefilter::Prefilter;
+use pretty::Pretty;
+use rewrite::Rewrite;
+use stats::{Clock, Origins, Provenance, RewriteStats};
 use visitor::JsVisitor;
 
-pub type OxcDiagnostic = String;
-
 pub struct Rewriter<E: UrlRewriter> {
     cfg: Config,
     url: E,
+    passes: Vec<Box<dyn RewritePass>>,
+    rules: Vec<PatchRule>,
+    init_scripts: Vec<InitScript>,
+    clock: Clock,
+    /// Whether `clock` tells the time, see [`stats::HAS_DEFAULT_CLOCK`].
+    has_clock: bool,
+    prefilter: Prefilter,
+    /// Kept between calls so the arena's memory is reused.
+    alloc: Mutex<Allocator>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewriteOutcome {
+    Rewritten,
+    /// The input had syntax errors and what the parser recovered was
+    /// rewritten anyway.
+    BestEffort,
+    /// The input had syntax errors and was returned untouched.
+    Passthrough,
+    /// The prefilter found nothing to rewrite, so the input was not parsed and
+    /// only gained the source tag and `sourceURL`. `errors` holds no parse
+    /// errors.
+    Prefiltered,
+    /// The script went over a budget and was returned untouched, see
+    /// `Flags::over_budget`.
+    OverBudget(Budget),
 }
 
 #[derive(Debug)]
 pub struct RewriteResult {
     pub js: Vec<u8>,
     pub sourcemap: Vec<u8>,
-    pub errors: Vec<OxcDiagnostic>,
+    pub errors: Vec<Diagnostic>,
+    /// What the script was rewritten as, resolved if `Flags::is_module` was
+    /// `ScriptKind::Auto`.
+    pub kind: ScriptKind,
+    pub outcome: RewriteOutcome,
+    /// What `Flags::verify` found wrong with the output.
+    pub regressions: Vec<Diagnostic>,
+    /// Names of the patch rules that changed something, leaving out those
+    /// whose rewrites were all dropped for conflicting with another rule.
+    pub applied_rules: Vec<String>,
+    /// Names of the init scripts inserted.
+    pub injected_scripts: Vec<String>,
+    pub stats: RewriteStats,
+    /// Every rewrite and what produced it, when `Flags::provenance` is set.
+    pub provenance: Vec<Provenance>,
+    /// WHATWG name of the encoding the input was decoded as. `js` is always
+    /// UTF-8 whatever this says, and should be served as such.
+    pub source_encoding: &'static str,
+    /// The output formatted for reading, when `Flags::pretty` is set.
+    pub pretty: Option<Pretty>,
     pub flags: Flags,
 }
 
+impl RewriteResult {
+    /// A result with nothing beyond the output and how it came about.
+    fn new(
+        js: Vec<u8>,
+        sourcemap: Vec<u8>,
+        errors: Vec<Diagnostic>,
+        kind: ScriptKind,
+        outcome: RewriteOutcome,
+        stats: RewriteStats,
+        flags: Flags,
+    ) -> Self {
+        Self {
+            js,
+            sourcemap,
+            errors,
+            kind,
+            outcome,
+            regressions: Vec::new(),
+            applied_rules: Vec::new(),
+            injected_scripts: Vec::new(),
+            stats,
+            provenance: Vec::new(),
+            source_encoding: UTF_8.name(),
+            pretty: None,
+            flags,
+        }
+    }
+
+    /// `js` handed back untouched, without a sourcemap.
+    fn passthrough(
+        js: &str,
+        errors: Vec<Diagnostic>,
+        kind: ScriptKind,
+        outcome: RewriteOutcome,
+        mut stats: RewriteStats,
+        flags: Flags,
+    ) -> Self {
+        stats.output_size = js.len();
+        let js = js.as_bytes().to_vec();
+        Self::new(js, Vec::new(), errors, kind, outcome, stats, flags)
+    }
+}
+
 impl<E: UrlRewriter> Rewriter<E> {
     pub fn new(cfg: Config, url: E) -> Self {
-        Self { cfg, url }
+        Self {
+            prefilter: Prefilter::new(&cfg),
+            alloc: Mutex::default(),
+            cfg,
+            url,
+            passes: Vec::new(),
+            rules: Vec::new(),
+            init_scripts: Vec::new(),
+            clock: stats::default_clock,
+            has_clock: stats::HAS_DEFAULT_CLOCK,
+        }
+    }
+
+    /// Replaces the clock `RewriteResult::stats` timings are taken with.
+    /// Time budgets need one on wasm32, which has no default.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+        self.has_clock = true;
+    }
+
+    /// Adds patch rules, applied after all passes to scripts whose URL they
+    /// match.
+    pub fn add_patch_rules(&mut self, rules: impl IntoIterator<Item = PatchRule>) {
+        self.rules.extend(rules);
+    }
+
+    /// Adds patch rules from a JSON array, see [`PatchRule`].
+    pub fn load_patch_rules(&mut self, json: &str) -> Result<()> {
+        let rules = patch::parse_rules(json).map_err(|e| anyhow!("invalid patch rules: {e}"))?;
+        self.add_patch_rules(rules);
+        Ok(())
+    }
+
+    pub fn patch_rules(&self) -> &[PatchRule] {
+        &self.rules
+    }
+
+    /// Adds init scripts, inserted in order at the start of scripts whose URL
+    /// and kind they match.
+    pub fn add_init_scripts(&mut self, scripts: impl IntoIterator<Item = InitScript>) {
+        self.init_scripts.extend(scripts);
+    }
+
+    /// Adds init scripts from a JSON array, see [`InitScript`].
+    pub fn load_init_scripts(&mut self, json: &str) -> Result<()> {
+        let scripts =
+            init::parse_init_scripts(json).map_err(|e| anyhow!("invalid init scripts: {e}"))?;
+        self.add_init_scripts(scripts);
+        Ok(())
+    }
+
+    pub fn init_scripts(&self) -> &[InitScript] {
+        &self.init_scripts
+    }
+
+    /// Adds a pass to run after the built-in ones and any added before it.
+    pub fn add_pass(&mut self, pass: impl RewritePass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    pub fn passes(&self) -> impl Iterator<Item = &dyn RewritePass> {
+        self.passes.iter().map(|p| p.as_ref())
     }
 
     pub fn config(&self) -> &Config {
@@ -40,37 +232,597 @@ impl<E: UrlRewriter> Rewriter<E> {
             flags.base = "about:blank".to_string();
         }
 
-        let alloc = Allocator::default();
-        let source_type = SourceType::default().with_module(flags.is_module);
-        let parser = Parser::new(&alloc, js, source_type);
-        let parsed = parser.parse();
+        let mut stats = RewriteStats {
+            input_size: js.len(),
+            ..RewriteStats::default()
+        };
+        let started = (self.clock)();
+        if flags.max_input_size.is_some_and(|max| js.len() > max) {
+            let kind = flags.is_module;
+            return self.over_budget(js, flags, kind, Budget::InputSize, Vec::new(), stats);
+        }
+        let deadline = self.deadline(started, &flags)?;
+
+        let skips_parse = self.skips_parse(js, &flags);
+        let prefiltered_at = (self.clock)();
+        stats.prefilter = prefiltered_at.saturating_sub(started);
+        if let Some((kind, points)) = skips_parse {
+            flags.is_module = kind;
+            if flags.sourcetag.is_empty() {
+                flags.sourcetag = self.source_tag(js, &flags);
+            }
+            let mut origins = Origins::default();
+            let rewrites = visitor::unparsed_rewrites(js, &flags, points, &mut origins);
+            if flags.max_changes.is_some_and(|max| rewrites.len() > max) {
+                return self.over_budget(js, flags, kind, Budget::Changes, Vec::new(), stats);
+            }
+            let (out, provenance) = self.apply(
+                js,
+                &flags,
+                points,
+                rewrites,
+                &origins,
+                &mut stats,
+                prefiltered_at,
+            );
+            let pretty = self.pretty(js, &flags, &out);
+            let outcome = RewriteOutcome::Prefiltered;
+            return Ok(RewriteResult {
+                provenance,
+                pretty,
+                ..RewriteResult::new(
+                    out.output,
+                    out.sourcemap,
+                    Vec::new(),
+                    kind,
+                    outcome,
+                    stats,
+                    flags,
+                )
+            });
+        }
+
+        let arena = self.arena();
+        let (parsed, kind) = parse(&arena, strip_bom(js), flags.is_module);
+        let parsed_at = (self.clock)();
+        stats.parse = parsed_at.saturating_sub(prefiltered_at);
+        flags.is_module = kind;
+        if flags.sourcetag.is_empty() {
+            flags.sourcetag = self.source_tag(js, &flags);
+        }
+        let points = InjectionPoints::new(js, &parsed.program);
 
+        let lines = LineIndex::new(js);
         let errors = parsed
             .errors
             .into_iter()
-            .map(|e| format!("{e:?}"))
+            .map(|e| Diagnostic::from_oxc(e, &lines, points.bom, &flags.url))
             .collect::<Vec<_>>();
 
-        let visitor = JsVisitor::new(js, &self.cfg, &flags, &self.url);
-        let rewrites = visitor.run();
+        let outcome = if errors.iter().any(|e| e.severity == Severity::Error) {
+            match flags.invalid_js {
+                InvalidJsPolicy::Passthrough => {
+                    let outcome = RewriteOutcome::Passthrough;
+                    return Ok(RewriteResult::passthrough(
+                        js, errors, kind, outcome, stats, flags,
+                    ));
+                }
+                InvalidJsPolicy::BestEffort => RewriteOutcome::BestEffort,
+                InvalidJsPolicy::Reject => return Err(RewriteError::InvalidJs { errors }.into()),
+            }
+        } else {
+            RewriteOutcome::Rewritten
+        };
+        if deadline.exceeded() {
+            return self.over_budget(js, flags, kind, deadline.budget(), errors, stats);
+        }
 
-        let mut transformer: Transformer<'_, '_, JsChange> = Transformer::default();
-        for rewrite in rewrites {
-            transformer.extend(rewrite.into_inner(&self.cfg));
+        let visitor = JsVisitor::new(
+            js,
+            &parsed.program,
+            &self.cfg,
+            &flags,
+            &self.url,
+            points,
+            &deadline,
+        );
+        let mut origins = Origins::default();
+        let mut rewrites = visitor.run_traced(&mut origins);
+        if deadline.exceeded() {
+            return self.over_budget(js, flags, kind, deadline.budget(), errors, stats);
         }
 
-        let out = transformer.perform(js, &self.cfg);
+        let mut injected_scripts = Vec::new();
+        let matching = self
+            .init_scripts
+            .iter()
+            .filter(|s| s.matches(&flags.url, kind));
+        for script in matching {
+            rewrites.push(script.rewrite(points, &flags));
+            origins.mark(rewrites.len(), format!("init:{}", script.name));
+            injected_scripts.push(script.name.clone());
+        }
+
+        let builtin = rewrites.len();
+        let mut applied_rules = Vec::new();
+        if !self.passes.is_empty() || !self.rules.is_empty() {
+            let semantic = SemanticBuilder::new().build(&parsed.program).semantic;
+            let cx = PassContext {
+                src: js,
+                program: &parsed.program,
+                semantic: &semantic,
+                cfg: &self.cfg,
+                flags: &flags,
+                points,
+            };
+            for pass in &self.passes {
+                rewrites.extend(
+                    pass.run(&cx)
+                        .into_iter()
+                        .filter(|r| r.span.start >= points.comment),
+                );
+                origins.mark(rewrites.len(), pass.name());
+                if deadline.exceeded() {
+                    return self.over_budget(js, flags, kind, deadline.budget(), errors, stats);
+                }
+            }
+            for rule in &self.rules {
+                let before = rewrites.len();
+                rewrites.extend(
+                    rule.apply(&cx)
+                        .into_iter()
+                        .filter(|r| r.span.start >= points.comment),
+                );
+                if rewrites.len() > before {
+                    origins.mark(rewrites.len(), format!("patch:{}", rule.name));
+                    applied_rules.push(rule.name.clone());
+                }
+                if deadline.exceeded() {
+                    return self.over_budget(js, flags, kind, deadline.budget(), errors, stats);
+                }
+            }
+            let keep = conflict::resolve(&rewrites, builtin, &self.cfg, &flags);
+            origins.retain(&mut rewrites, &keep);
+            applied_rules.retain(|name| origins.contains(&format!("patch:{name}")));
+            injected_scripts.retain(|name| origins.contains(&format!("init:{name}")));
+        }
+
+        if flags.max_changes.is_some_and(|max| rewrites.len() > max) {
+            return self.over_budget(js, flags, kind, Budget::Changes, errors, stats);
+        }
+
+        let (out, provenance) = self.apply(
+            js, &flags, points, rewrites, &origins, &mut stats, parsed_at,
+        );
+
+        let pretty = self.pretty(js, &flags, &out);
+        let regressions = match std::str::from_utf8(&out.output) {
+            Ok(output) if flags.verify => {
+                let positions = out.original_positions();
+                verify::verify(output, &errors, &positions, &self.cfg, &flags)
+            }
+            _ => Vec::new(),
+        };
 
         Ok(RewriteResult {
+            regressions,
+            applied_rules,
+            injected_scripts,
+            provenance,
+            pretty,
+            ..RewriteResult::new(
+                out.output,
+                out.sourcemap,
+                errors,
+                kind,
+                outcome,
+                stats,
+                flags,
+            )
+        })
+    }
+
+    /// See [`tag::source_tag`].
+    fn source_tag(&self, js: &str, flags: &Flags) -> String {
+        tag::source_tag(
+            js,
+            &self.cfg,
+            flags,
+            self.passes().map(|p| p.name()),
+            &self.rules,
+            &self.init_scripts,
+        )
+    }
+
+    /// Tracks the time and step budgets of `flags` from `started`.
+    fn deadline(&self, started: Duration, flags: &Flags) -> Result<Deadline> {
+        if flags.time_budget.is_some() && !self.has_clock {
+            return Err(RewriteError::NoClock.into());
+        }
+        Ok(Deadline::new(
+            self.clock,
+            started,
+            flags.time_budget,
+            flags.max_steps,
+        ))
+    }
+
+    /// Returns `js` untouched with a warning, or fails, per
+    /// `Flags::over_budget`.
+    fn over_budget(
+        &self,
+        js: &str,
+        flags: Flags,
+        kind: ScriptKind,
+        budget: Budget,
+        mut errors: Vec<Diagnostic>,
+        stats: RewriteStats,
+    ) -> Result<RewriteResult> {
+        if flags.over_budget == BudgetPolicy::Reject {
+            return Err(RewriteError::OverBudget { budget }.into());
+        }
+        errors.push(Diagnostic::whole(
+            Severity::Warning,
+            diagnostic::BUDGET,
+            format!("rewrite went over its {budget} budget, returned untouched"),
+            &flags.url,
+        ));
+        let outcome = RewriteOutcome::OverBudget(budget);
+        Ok(RewriteResult::passthrough(
+            js, errors, kind, outcome, stats, flags,
+        ))
+    }
+
+    /// Looks for ways `js` may get around the rewrite, without rewriting it.
+    pub fn audit(&self, js: &str, flags: &Flags) -> Audit {
+        let (findings, kind, errors) = self.analyze(js, flags, audit::audit);
+        Audit {
+            findings,
+            errors,
+            kind,
+        }
+    }
+
+    /// Lists the browser APIs `js` uses, without rewriting it.
+    pub fn inventory(&self, js: &str, flags: &Flags) -> Inventory {
+        let (uses, kind, errors) = self.analyze(js, flags, inventory::inventory);
+        Inventory { uses, errors, kind }
+    }
+
+    /// Parses `js` and runs `f` over it, for analyses that don't rewrite.
+    /// Also gives the resolved kind and the parse errors.
+    fn analyze<R>(
+        &self,
+        js: &str,
+        flags: &Flags,
+        f: impl FnOnce(&PassContext<'_>) -> R,
+    ) -> (R, ScriptKind, Vec<Diagnostic>) {
+        let arena = self.arena();
+        let (parsed, kind) = parse(&arena, strip_bom(js), flags.is_module);
+        let points = InjectionPoints::new(js, &parsed.program);
+        let lines = LineIndex::new(js);
+        let errors = parsed
+            .errors
+            .into_iter()
+            .map(|e| Diagnostic::from_oxc(e, &lines, points.bom, &flags.url))
+            .collect();
+        let semantic = SemanticBuilder::new().build(&parsed.program).semantic;
+        let cx = PassContext {
+            src: js,
+            program: &parsed.program,
+            semantic: &semantic,
+            cfg: &self.cfg,
+            flags,
+            points,
+        };
+        (f(&cx), kind, errors)
+    }
+
+    /// The kept arena, emptied, or a fresh one when another call holds it.
+    fn arena(&self) -> Arena<'_> {
+        match self.alloc.try_lock() {
+            Ok(mut alloc) => {
+                alloc.reset();
+                Arena::Reused(alloc)
+            }
+            Err(_) => Arena::Fresh(Allocator::default()),
+        }
+    }
+
+    /// Whether the script can be handled without a parse: the prefilter finds
+    /// nothing for the visitor, and nothing else that needs the AST applies.
+    /// Gives the resolved kind and the injection points.
+    fn skips_parse(&self, js: &str, flags: &Flags) -> Option<(ScriptKind, InjectionPoints)> {
+        if !flags.prefilter
+            || flags.verify
+            || flags.invalid_js != InvalidJsPolicy::BestEffort
+            || !self.passes.is_empty()
+            || !self.rules.is_empty()
+            || self.prefilter.needs_rewrite(js, flags.is_module)
+        {
+            return None;
+        }
+        // Without `import`, `export` or `await` there is no module syntax.
+        let kind = match flags.is_module {
+            ScriptKind::Auto => ScriptKind::Script,
+            kind => kind,
+        };
+        if self
+            .init_scripts
+            .iter()
+            .any(|s| s.matches(&flags.url, kind))
+        {
+            return None;
+        }
+        Some((kind, InjectionPoints::lexical(js)?))
+    }
+
+    /// Applies `rewrites` and fills in the rest of `stats`, timing the visit
+    /// from `visit_start`.
+    #[allow(clippy::too_many_arguments)]
+    fn apply<'a>(
+        &'a self,
+        js: &'a str,
+        flags: &'a Flags,
+        points: InjectionPoints,
+        rewrites: Vec<Rewrite>,
+        origins: &Origins,
+        stats: &mut RewriteStats,
+        visit_start: Duration,
+    ) -> (TransformOutput<'a>, Vec<Provenance>) {
+        for rewrite in &rewrites {
+            *stats.counts.entry(rewrite.ty.name()).or_default() += 1;
+        }
+        let provenance = if flags.provenance {
+            origins.provenance(&rewrites)
+        } else {
+            Vec::new()
+        };
+        let visited_at = (self.clock)();
+        stats.visit = visited_at.saturating_sub(visit_start);
+
+        let mut transformer: Transformer<'_, JsChange> = Transformer::default();
+        for rewrite in rewrites {
+            transformer.extend(rewrite.into_inner(&self.cfg, flags));
+        }
+
+        let mut out = transformer.perform(js, &self.cfg);
+        if flags.do_sourcemaps && flags.inline_sourcemaps {
+            self.push_sourcemap(&mut out, points, flags);
+        }
+        stats.transform = (self.clock)().saturating_sub(visited_at);
+        stats.output_size = out.output.len();
+        (out, provenance)
+    }
+
+    fn pretty(&self, js: &str, flags: &Flags, out: &TransformOutput) -> Option<Pretty> {
+        if !flags.pretty {
+            return None;
+        }
+        let output = std::str::from_utf8(&out.output).ok()?;
+        let positions = out.original_positions();
+        pretty::pretty(js, output, &positions, flags.is_module, &flags.url)
+    }
+
+    /// Registers the sourcemap from inside the script, ahead of any other code
+    /// so functions can be mapped as soon as they exist. The call is part of
+    /// the output, so it is recorded in the map it carries; that works because
+    /// the encoded map has the same size wherever its records point. After a
+    /// hashbang that runs to the end of the input, the call has to start a
+    /// line of its own or the hashbang would comment it out.
+    fn push_sourcemap(&self, out: &mut TransformOutput, points: InjectionPoints, flags: &Flags) {
+        let head = format!(
+            "{}{}{}(\"{}\",\"",
+            if points.comment_newline { "\n" } else { "" },
+            if points.code_semicolon { ";" } else { "" },
+            self.cfg.pushsourcemapfn,
+            flags.sourcetag
+        );
+        let tail = "\");";
+
+        let map_len = encoded_map_len(&out.records) + INSERT_RECORD_LEN;
+        let size = head.len() + base64::encoded_len(map_len, true).unwrap_or(0) + tail.len();
+
+        let pos = out.output_pos(points.code);
+        out.splice(pos, &vec![b' '; size]);
+        out.sourcemap = encode_map(&out.records);
+
+        let mut text = head;
+        STANDARD.encode_string(&out.sourcemap, &mut text);
+        text.push_str(tail);
+        debug_assert_eq!(text.len(), size);
+        out.output[pos as usize..pos as usize + size].copy_from_slice(text.as_bytes());
+    }
+
+    /// Rewrites the body of an inline event handler such as `onclick`, which
+    /// is compiled as `function(event) { ... }` running inside
+    /// `with (document) with (form) with (element)`, so it may `return` and
+    /// its free names may resolve to element properties. Gives the body alone
+    /// with its map. Init scripts, passes and patch rules don't apply.
+    pub fn rewrite_handler(&self, body: &str, mut flags: Flags) -> Result<HandlerResult> {
+        if flags.base.is_empty() {
+            flags.base = "about:blank".to_string();
+        }
+        flags.is_module = ScriptKind::Script;
+        let untouched = |errors, outcome| HandlerResult {
+            js: body.as_bytes().to_vec(),
+            sourcemap: Vec::new(),
+            errors,
+            outcome,
+        };
+        let started = (self.clock)();
+        if flags.max_input_size.is_some_and(|max| body.len() > max) {
+            return self.handler_over_budget(body, &flags, Budget::InputSize);
+        }
+        let deadline = self.deadline(started, &flags)?;
+
+        let wrapped = handler::wrap(body);
+        let arena = self.arena();
+        let (parsed, _) = parse(&arena, &wrapped, ScriptKind::Script);
+        let wrapped_lines = LineIndex::new(&wrapped);
+        let lines = LineIndex::new(body);
+        let mut errors = parsed
+            .errors
+            .into_iter()
+            .map(|e| Diagnostic::from_oxc(e, &wrapped_lines, 0, &flags.url))
+            .map(|d| handler::unwrap_diagnostic(d, &lines))
+            .collect::<Vec<_>>();
+        if errors.is_empty() && !handler::contains_body(&parsed.program, body) {
+            errors.push(handler::escaped(&flags.url));
+        }
+
+        let outcome = if errors.iter().any(|e| e.severity == Severity::Error) {
+            match flags.invalid_js {
+                InvalidJsPolicy::Passthrough => {
+                    return Ok(untouched(errors, RewriteOutcome::Passthrough));
+                }
+                // What was recovered from a body that got out of its function
+                // can't be told apart from the wrapper.
+                InvalidJsPolicy::BestEffort if handler::contains_body(&parsed.program, body) => {
+                    RewriteOutcome::BestEffort
+                }
+                InvalidJsPolicy::BestEffort => {
+                    return Ok(untouched(errors, RewriteOutcome::Passthrough));
+                }
+                InvalidJsPolicy::Reject => return Err(RewriteError::InvalidJs { errors }.into()),
+            }
+        } else {
+            RewriteOutcome::Rewritten
+        };
+
+        let points = InjectionPoints::new(&wrapped, &parsed.program);
+        let visitor = JsVisitor::new(
+            &wrapped,
+            &parsed.program,
+            &self.cfg,
+            &flags,
+            &self.url,
+            points,
+            &deadline,
+        );
+        let rewrites = handler::unwrap_rewrites(visitor.run(), body);
+        if deadline.exceeded() {
+            return self.handler_over_budget(body, &flags, deadline.budget());
+        }
+        if flags.max_changes.is_some_and(|max| rewrites.len() > max) {
+            return self.handler_over_budget(body, &flags, Budget::Changes);
+        }
+
+        let mut transformer: Transformer<'_, JsChange> = Transformer::default();
+        for rewrite in rewrites {
+            transformer.extend(rewrite.into_inner(&self.cfg, &flags));
+        }
+        let out = transformer.perform(body, &self.cfg);
+        Ok(HandlerResult {
             js: out.output,
             sourcemap: out.sourcemap,
             errors,
-            flags,
+            outcome,
+        })
+    }
+
+    fn handler_over_budget(
+        &self,
+        body: &str,
+        flags: &Flags,
+        budget: Budget,
+    ) -> Result<HandlerResult> {
+        if flags.over_budget == BudgetPolicy::Reject {
+            return Err(RewriteError::OverBudget { budget }.into());
+        }
+        Ok(HandlerResult {
+            js: body.as_bytes().to_vec(),
+            sourcemap: Vec::new(),
+            errors: vec![Diagnostic::whole(
+                Severity::Warning,
+                diagnostic::BUDGET,
+                format!("rewrite went over its {budget} budget, returned untouched"),
+                &flags.url,
+            )],
+            outcome: RewriteOutcome::OverBudget(budget),
         })
     }
 
+    /// Rewrites a `javascript:` URL, decoded first the way a browser decodes
+    /// it so percent-encoded code can't get past, see
+    /// [`javascript_url::script`]. The script is rewritten as a classic script
+    /// and its completion value is still what the URL evaluates to. Anything
+    /// that isn't a `javascript:` URL comes back unchanged, and a script that
+    /// can't be rewritten gives a URL that does nothing.
+    pub fn rewrite_javascript_url(&self, url: &str, mut flags: Flags) -> String {
+        let Some(script) = javascript_url::script(url) else {
+            return url.to_string();
+        };
+        flags.is_module = ScriptKind::Script;
+        // The registration call goes after the directive prologue, so a URL
+        // that is a lone string would evaluate to its result instead.
+        flags.inline_sourcemaps = false;
+        match self.rewrite(&script, flags) {
+            Ok(result) if !matches!(result.outcome, RewriteOutcome::OverBudget(_)) => {
+                javascript_url::encode(&String::from_utf8_lossy(&result.js))
+            }
+            _ => javascript_url::encode("void 0"),
+        }
+    }
+
+    /// Decodes `js` per `Flags::charset` and any BOM, see [`charset::decode`],
+    /// and rewrites it. Positions in the result are in the decoded text.
     pub fn rewrite_bytes(&self, js: &[u8], flags: Flags) -> Result<RewriteResult> {
-        let text = std::str::from_utf8(js).map_err(|e| anyhow!("input js is not utf-8: {e}"))?;
-        self.rewrite(text, flags)
+        let decoded = charset::decode(js, flags.charset.as_deref());
+        let mut result = self.rewrite(&decoded.text, flags)?;
+        result.source_encoding = decoded.encoding.name();
+        if decoded.malformed {
+            result.errors.push(Diagnostic::whole(
+                Severity::Warning,
+                diagnostic::CHARSET,
+                format!(
+                    "input is not valid {}, malformed bytes were replaced",
+                    decoded.encoding.name()
+                ),
+                &result.flags.url,
+            ));
+        }
+        Ok(result)
+    }
+}
+
+enum Arena<'a> {
+    Reused(MutexGuard<'a, Allocator>),
+    Fresh(Allocator),
+}
+
+impl Deref for Arena<'_> {
+    type Target = Allocator;
+
+    fn deref(&self) -> &Allocator {
+        match self {
+            Self::Reused(alloc) => alloc,
+            Self::Fresh(alloc) => alloc,
+        }
+    }
+}
+
+fn parse<'a>(
+    alloc: &'a Allocator,
+    js: &'a str,
+    kind: ScriptKind,
+) -> (ParserReturn<'a>, ScriptKind) {
+    let source_type = match kind {
+        ScriptKind::Auto => SourceType::unambiguous(),
+        ScriptKind::Module => SourceType::mjs(),
+        // `SourceType::default()` is a module.
+        ScriptKind::Script => SourceType::script(),
+    };
+    let parsed = Parser::new(alloc, js, source_type).parse();
+    let detected = ScriptKind::from(parsed.program.source_type.is_module());
+
+    // oxc only counts import/export/import.meta as module syntax, so a script
+    // that failed to parse might still be a module using top-level await.
+    if kind == ScriptKind::Auto && detected == ScriptKind::Script && !parsed.errors.is_empty() {
+        let module = Parser::new(alloc, js, SourceType::mjs()).parse();
+        if module.errors.is_empty() {
+            return (module, ScriptKind::Module);
+        }
     }
-}
\ No newline at end of file
+    (parsed, detected)
+}
@@ -0,0 +1,45 @@
+use oxc::{ast::ast::Program, semantic::Semantic, span::Span};
+
+use crate::{
+    cfg::{Config, Flags},
+    injection::InjectionPoints,
+    rewrite::Rewrite,
+};
+
+/// Everything a pass gets to look at.
+pub struct PassContext<'a> {
+    /// The whole input, which `Rewrite` spans index into.
+    pub src: &'a str,
+    pub program: &'a Program<'a>,
+    pub semantic: &'a Semantic<'a>,
+    pub cfg: &'a Config,
+    pub flags: &'a Flags,
+    pub points: InjectionPoints,
+}
+
+impl PassContext<'_> {
+    /// AST spans don't count a leading BOM; this turns one into a span that
+    /// can go in a `Rewrite`.
+    pub fn source_span(&self, span: Span) -> Span {
+        Span::new(span.start + self.points.bom, span.end + self.points.bom)
+    }
+}
+
+/// A rewrite step added on top of the built-in ones, see
+/// [`crate::Rewriter::add_pass`].
+///
+/// Passes run after the built-in visitor, in the order they were added, and
+/// before patch rules. Their rewrites go through the same transformer and
+/// sourcemap as the built-in ones, applied in offset order, those at the same
+/// offset in the order they were produced.
+///
+/// Overlaps are settled a whole rewrite at a time, never by dropping half of
+/// a wrap. A pass rewrite that replaces text a built-in rewrite changes, or
+/// whose span crosses one, takes the place of that built-in rewrite. One that
+/// conflicts like that with a rewrite of an earlier pass or rule is dropped.
+pub trait RewritePass: Send + Sync {
+    /// Identifies the pass in diagnostics.
+    fn name(&self) -> &str;
+
+    fn run(&self, cx: &PassContext<'_>) -> Vec<Rewrite>;
+}
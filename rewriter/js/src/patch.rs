@@ -0,0 +1,190 @@
+use oxc::{
+    ast::AstKind,
+    span::{GetSpan, Span},
+};
+use serde::Deserialize;
+
+use crate::{
+    pass::PassContext,
+    rewrite::{Rewrite, RewriteType},
+};
+
+/// A targeted fix for scripts from particular sites, loaded from JSON:
+///
+/// ```json
+/// {
+///   "name": "no-battery",
+///   "host": "*.example.com",
+///   "call": "navigator.getBattery",
+///   "action": "replace",
+///   "text": "Promise.reject(new Error())"
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct PatchRule {
+    /// Reported in `RewriteResult::applied_rules` when the rule changes
+    /// something.
+    pub name: String,
+    /// Glob over the script URL, `*` matching anything.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Glob over the host of the script URL.
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(flatten)]
+    pub target: PatchTarget,
+    pub action: PatchAction,
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchTarget {
+    /// Every occurrence of this exact text.
+    Snippet(String),
+    /// Calls whose callee is written exactly like this, e.g. `a.b.c`.
+    Call(String),
+    /// Member expressions written exactly like this.
+    Member(String),
+    /// References to this identifier.
+    Identifier(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PatchAction {
+    Replace,
+    InsertBefore,
+    InsertAfter,
+    Delete,
+}
+
+pub fn parse_rules(json: &str) -> serde_json::Result<Vec<PatchRule>> {
+    serde_json::from_str(json)
+}
+
+impl PatchRule {
+    pub fn matches_url(&self, url: &str) -> bool {
+        url_matches(self.url.as_deref(), self.host.as_deref(), url)
+    }
+
+    pub fn apply(&self, cx: &PassContext<'_>) -> Vec<Rewrite> {
+        if !self.matches_url(&cx.flags.url) {
+            return Vec::new();
+        }
+        self.targets(cx)
+            .into_iter()
+            .map(|span| self.rewrite(span))
+            .collect()
+    }
+
+    fn targets(&self, cx: &PassContext<'_>) -> Vec<Span> {
+        let text = |span: Span| span.source_text(cx.program.source_text);
+        if let PatchTarget::Snippet(snippet) = &self.target {
+            if snippet.is_empty() {
+                return Vec::new();
+            }
+            return cx
+                .src
+                .match_indices(snippet.as_str())
+                .map(|(i, s)| Span::new(i as u32, (i + s.len()) as u32))
+                .collect();
+        }
+
+        cx.semantic
+            .nodes()
+            .iter()
+            .filter_map(|node| match (&self.target, node.kind()) {
+                (PatchTarget::Call(callee), AstKind::CallExpression(call))
+                    if text(call.callee.span()) == callee =>
+                {
+                    Some(call.span)
+                }
+                (PatchTarget::Member(member), AstKind::StaticMemberExpression(m))
+                    if text(m.span) == member =>
+                {
+                    Some(m.span)
+                }
+                (PatchTarget::Member(member), AstKind::ComputedMemberExpression(m))
+                    if text(m.span) == member =>
+                {
+                    Some(m.span)
+                }
+                (PatchTarget::Identifier(name), AstKind::IdentifierReference(ident))
+                    if ident.name == name.as_str() =>
+                {
+                    Some(ident.span)
+                }
+                _ => None,
+            })
+            .map(|span| cx.source_span(span))
+            .collect()
+    }
+
+    fn rewrite(&self, span: Span) -> Rewrite {
+        let (span, ty) = match self.action {
+            PatchAction::Replace => (
+                span,
+                RewriteType::Replace {
+                    text: self.text.clone(),
+                },
+            ),
+            PatchAction::Delete => (span, RewriteType::Delete),
+            PatchAction::InsertBefore => (
+                Span::new(span.start, span.start),
+                RewriteType::Replace {
+                    text: self.text.clone(),
+                },
+            ),
+            PatchAction::InsertAfter => (
+                Span::new(span.end, span.end),
+                RewriteType::Replace {
+                    text: self.text.clone(),
+                },
+            ),
+        };
+        Rewrite { span, ty }
+    }
+}
+
+/// Whether `url` matches both an optional glob over the whole URL and one
+/// over its host.
+pub(crate) fn url_matches(
+    url_pattern: Option<&str>,
+    host_pattern: Option<&str>,
+    url: &str,
+) -> bool {
+    url_pattern.is_none_or(|p| glob(p, url)) && host_pattern.is_none_or(|p| glob(p, host(url)))
+}
+
+fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    if host.starts_with('[') {
+        return host.split_inclusive(']').next().unwrap_or(host);
+    }
+    host.split(':').next().unwrap_or(host)
+}
+
+/// Matches `text` against a pattern where `*` stands for any run of
+/// characters.
+fn glob(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts = parts.collect::<Vec<_>>();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
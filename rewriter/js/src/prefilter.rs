@@ -0,0 +1,95 @@
+use crate::cfg::{Config, ScriptKind};
+
+/// Proves that the visitor has nothing to rewrite in a script without
+/// parsing it, by searching for everything the visitor reacts to in a single
+/// pass over the script. Matches are not checked against strings and
+/// comments, so this errs on the side of reporting work.
+#[derive(Debug, Clone)]
+pub struct Prefilter {
+    needles: Vec<(Vec<u8>, Needle)>,
+    /// Indices into `needles` by their first byte, so each byte of the
+    /// script is only compared with the needles that could start there.
+    by_first: Vec<Vec<usize>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Needle {
+    /// An identifier the visitor rewrites, matched on token boundaries.
+    Name,
+    /// Text the visitor looks for anywhere.
+    Raw,
+    /// Syntax that makes `ScriptKind::Auto` resolve to a module.
+    Module,
+}
+
+impl Prefilter {
+    pub fn new(cfg: &Config) -> Self {
+        let mut names = cfg.unsafe_globals.clone();
+        names.extend(cfg.unsafe_properties.iter().cloned());
+        names.push("postMessage".into());
+        // Call sites whose URL arguments the visitor rewrites.
+        names.extend(
+            [
+                "Worker",
+                "SharedWorker",
+                "serviceWorker",
+                "paintWorklet",
+                "audioWorklet",
+                "importScripts",
+            ]
+            .map(String::from),
+        );
+        names.sort();
+        names.dedup();
+
+        let needles = names
+            .into_iter()
+            .map(|n| (n.into_bytes(), Needle::Name))
+            .chain(["import", "debugger", "sourceURL="].map(|n| (n.into(), Needle::Raw)))
+            .chain(["export", "await"].map(|n| (n.into(), Needle::Module)))
+            .filter(|(n, _)| !n.is_empty())
+            .collect::<Vec<_>>();
+        let mut by_first = vec![Vec::new(); 256];
+        for (i, (needle, _)) in needles.iter().enumerate() {
+            by_first[needle[0] as usize].push(i);
+        }
+        Self { needles, by_first }
+    }
+
+    /// Whether the visitor could rewrite anything in `js`.
+    pub fn needs_rewrite(&self, js: &str, kind: ScriptKind) -> bool {
+        let bytes = js.as_bytes();
+        (0..bytes.len()).any(|at| {
+            if bytes[at] == b'[' && is_member_bracket(bytes, at) {
+                return true;
+            }
+            self.by_first[bytes[at] as usize].iter().any(|&i| {
+                let (needle, what) = &self.needles[i];
+                bytes[at..].starts_with(needle)
+                    && match what {
+                        Needle::Name => is_token(bytes, at, at + needle.len()),
+                        Needle::Raw => true,
+                        Needle::Module => kind == ScriptKind::Auto,
+                    }
+            })
+        })
+    }
+}
+
+/// Whether the visitor's tokenizer could see `bytes[start..end]` as a whole
+/// identifier. A digit before it may or may not belong to an identifier.
+fn is_token(bytes: &[u8], start: usize, end: usize) -> bool {
+    let before = start.checked_sub(1).map(|i| bytes[i]);
+    let after = bytes.get(end).copied();
+    !before.is_some_and(|b| b.is_ascii_alphabetic() || b == b'_' || b == b'$')
+        && !after.is_some_and(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'$')
+}
+
+/// Mirrors when the visitor wraps a computed member access.
+fn is_member_bracket(bytes: &[u8], at: usize) -> bool {
+    let prev = bytes[..at]
+        .iter()
+        .rev()
+        .find(|b| !(**b as char).is_whitespace());
+    matches!(prev, Some(b) if b.is_ascii_alphanumeric() || b"_$.)]\"'".contains(b))
+}
@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+
+use oxc::{
+    allocator::Allocator,
+    codegen::{Codegen, CodegenOptions, IndentChar},
+    parser::Parser,
+    span::SourceType,
+};
+use oxc_sourcemap::SourceMapBuilder;
+use transform::OriginalPositions;
+
+use crate::{cfg::ScriptKind, diagnostic::LineIndex, injection::strip_bom};
+
+/// Formatted rewritten output, see `Flags::pretty`.
+#[derive(Debug, Clone)]
+pub struct Pretty {
+    pub code: String,
+    /// Source map v3 JSON from `code` to the input, with the input inlined.
+    pub map: String,
+}
+
+/// Formats `output`, the rewrite of `input`, mapping it back to `input`
+/// through `positions`. Injected code is left unmapped. Gives nothing when
+/// the output doesn't parse.
+pub fn pretty(
+    input: &str,
+    output: &str,
+    positions: &OriginalPositions,
+    kind: ScriptKind,
+    url: &str,
+) -> Option<Pretty> {
+    let alloc = Allocator::default();
+    let body = strip_bom(output);
+    let bom = (output.len() - body.len()) as u32;
+    let source_type = if kind.is_module() {
+        SourceType::mjs()
+    } else {
+        SourceType::script()
+    };
+    let parsed = Parser::new(&alloc, body, source_type).parse();
+    if !parsed.errors.is_empty() {
+        return None;
+    }
+    let name = if url.is_empty() { "input.js" } else { url };
+    let generated = Codegen::new()
+        .with_options(CodegenOptions {
+            source_map_path: Some(PathBuf::from(name)),
+            indent_char: IndentChar::Space,
+            indent_width: 2,
+            ..CodegenOptions::default()
+        })
+        .build(&parsed.program);
+
+    let mut builder = SourceMapBuilder::default();
+    let source = builder.add_source_and_content(name, input);
+    let body_lines = LineIndex::new(body);
+    let input_lines = LineIndex::new(input);
+    let mut from = Cursor::new(body, &body_lines);
+    let mut to = Cursor::new(input, &input_lines);
+    for token in generated.map.iter().flat_map(|map| map.get_tokens()) {
+        let offset = from.offset(token.get_src_line(), token.get_src_col()) + bom;
+        if positions.injected(offset) {
+            continue;
+        }
+        let (line, col) = to.position(positions.original(offset));
+        builder.add_token(
+            token.get_dst_line(),
+            token.get_dst_col(),
+            line,
+            col,
+            Some(source),
+            None,
+        );
+    }
+    Some(Pretty {
+        code: generated.code,
+        map: builder.into_sourcemap().to_json_string(),
+    })
+}
+
+/// Converts between byte offsets and 0-based line and UTF-16 column. Cheap
+/// when lookups move forward along a line, as sourcemap tokens mostly do,
+/// which matters for minified code on one long line.
+struct Cursor<'a> {
+    src: &'a str,
+    starts: &'a [u32],
+    line: u32,
+    col: u32,
+    offset: u32,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(src: &'a str, lines: &'a LineIndex<'a>) -> Self {
+        Self {
+            src,
+            starts: lines.starts(),
+            line: 0,
+            col: 0,
+            offset: 0,
+        }
+    }
+
+    fn seek_line(&mut self, line: u32) {
+        let line = line.min(self.starts.len() as u32 - 1);
+        self.line = line;
+        self.col = 0;
+        self.offset = self.starts[line as usize];
+    }
+
+    fn line_end(&self) -> u32 {
+        self.starts
+            .get(self.line as usize + 1)
+            .map_or(self.src.len() as u32, |&e| e)
+    }
+
+    fn offset(&mut self, line: u32, col: u32) -> u32 {
+        if line != self.line || col < self.col {
+            self.seek_line(line);
+        }
+        let end = self.line_end() as usize;
+        for c in self.src[self.offset as usize..end].chars() {
+            if self.col >= col {
+                break;
+            }
+            self.col += c.len_utf16() as u32;
+            self.offset += c.len_utf8() as u32;
+        }
+        self.offset
+    }
+
+    fn position(&mut self, offset: u32) -> (u32, u32) {
+        let mut offset = offset.min(self.src.len() as u32);
+        while !self.src.is_char_boundary(offset as usize) {
+            offset -= 1;
+        }
+        if offset < self.offset || offset >= self.line_end() {
+            let line = self.starts.partition_point(|&s| s <= offset) - 1;
+            self.seek_line(line as u32);
+        }
+        let skipped = &self.src[self.offset as usize..offset as usize];
+        self.col += skipped.encode_utf16().count() as u32;
+        self.offset = offset;
+        (self.line, self.col)
+    }
+}
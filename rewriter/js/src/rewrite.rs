@@ -1,6 +1,12 @@
 use oxc::span::Span;
 
-use crate::{changes::JsChange, cfg::Config};
+use smallvec::SmallVec;
+use transform::TransformElement;
+
+use crate::{
+    cfg::{Config, Flags},
+    changes::{JsChange, Parts, parts},
+};
 
 #[derive(Debug, Clone)]
 pub enum AssignmentOp {
@@ -13,28 +19,44 @@ pub enum AssignmentOp {
 
 #[derive(Debug, Clone)]
 pub enum RewriteType {
-    WrapFn { enclose: bool },
+    WrapFn {
+        enclose: bool,
+    },
     SetRealmFn,
     ImportFn,
     MetaFn,
-    RewriteProperty { ident: String },
-    RebindProperty { ident: String, tempvar: bool },
+    RewriteProperty {
+        ident: String,
+    },
+    RebindProperty {
+        ident: String,
+        tempvar: bool,
+    },
     TempVar,
     WrapObjectAssignment {
         restids: Vec<String>,
         location_assigned: bool,
     },
     WrapProperty,
-    RascalErr { ident: String },
+    RascalErr {
+        ident: String,
+    },
     Rascalitize,
-    Eval { inner: Span },
+    Eval {
+        inner: Span,
+    },
     Assignment {
         name: String,
         rhs_text: String,
         op: AssignmentOp,
     },
-    ShorthandObj { name: String },
+    ShorthandObj {
+        name: String,
+    },
     SourceTag,
+    SourceUrl {
+        url: String,
+    },
     CleanFunction {
         restids: Vec<String>,
         expression: bool,
@@ -45,7 +67,14 @@ pub enum RewriteType {
         restids: Vec<String>,
         location_assigned: bool,
     },
-    Replace { text: String },
+    Replace {
+        text: String,
+    },
+    /// Inserts code of the proxy's own at the start of the span, recorded
+    /// as injected so it maps to nothing in the source.
+    Inject {
+        text: String,
+    },
     Delete,
 }
 
@@ -55,121 +84,166 @@ pub struct Rewrite {
     pub ty: RewriteType,
 }
 
+impl RewriteType {
+    /// The variant name, used to count rewrites by kind.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::WrapFn { .. } => "WrapFn",
+            Self::SetRealmFn => "SetRealmFn",
+            Self::ImportFn => "ImportFn",
+            Self::MetaFn => "MetaFn",
+            Self::RewriteProperty { .. } => "RewriteProperty",
+            Self::RebindProperty { .. } => "RebindProperty",
+            Self::TempVar => "TempVar",
+            Self::WrapObjectAssignment { .. } => "WrapObjectAssignment",
+            Self::WrapProperty => "WrapProperty",
+            Self::RascalErr { .. } => "RascalErr",
+            Self::Rascalitize => "Rascalitize",
+            Self::Eval { .. } => "Eval",
+            Self::Assignment { .. } => "Assignment",
+            Self::ShorthandObj { .. } => "ShorthandObj",
+            Self::SourceTag => "SourceTag",
+            Self::SourceUrl { .. } => "SourceUrl",
+            Self::CleanFunction { .. } => "CleanFunction",
+            Self::CleanVariableDeclaration { .. } => "CleanVariableDeclaration",
+            Self::Replace { .. } => "Replace",
+            Self::Inject { .. } => "Inject",
+            Self::Delete => "Delete",
+        }
+    }
+}
+
 impl Rewrite {
-    pub fn into_inner(self, cfg: &Config) -> Vec<JsChange> {
+    /// Most of the text borrows from `cfg` and `flags`; only what the rewrite
+    /// carries itself is moved in.
+    pub fn into_inner<'a>(self, cfg: &'a Config, flags: &'a Flags) -> SmallVec<[JsChange<'a>; 2]> {
         use RewriteType as R;
-        let mut out: Vec<JsChange> = Vec::new();
+        let mut out = SmallVec::new();
         match self.ty {
             R::WrapFn { enclose } => {
-                let left = if enclose {
-                    format!("({}(", cfg.wrapfn)
-                } else {
-                    format!("{}(", cfg.wrapfn)
-                };
-                let right = if enclose { "))" } else { ")" };
-                out.push(JsChange::insert_left_owned(self.span, left));
-                out.push(JsChange::insert_right(self.span, right.to_string()));
+                let (left, right) = if enclose { ("(", "))") } else { ("", ")") };
+                out.push(JsChange::insert_left(
+                    self.span,
+                    parts![left, &cfg.wrapfn, "("],
+                ));
+                out.push(JsChange::insert_right(self.span, parts![right]));
             }
             R::SetRealmFn => {
-                out.push(JsChange::replace_owned(
+                out.push(JsChange::replace(
                     self.span,
-                    format!("{}({{}}).postMessage", cfg.setrealmfn),
+                    parts![&cfg.setrealmfn, "({}).postMessage"],
                 ));
             }
             R::ImportFn => {
-                out.push(JsChange::replace_owned(
+                out.push(JsChange::replace(
                     self.span,
-                    format!("{}(\"{}\",", cfg.importfn, cfg.prefix),
+                    parts![&cfg.importfn, "(\"", &cfg.prefix, "\","],
                 ));
             }
             R::MetaFn => {
-                out.push(JsChange::replace_owned(
+                out.push(JsChange::replace(
                     self.span,
-                    format!("{}(import.meta, \"{}\")", cfg.metafn, cfg.prefix),
+                    parts![&cfg.metafn, "(import.meta, \"", &cfg.prefix, "\")"],
                 ));
             }
             R::RewriteProperty { ident } => {
-                out.push(JsChange::replace_owned(
+                out.push(JsChange::replace(
                     self.span,
-                    format!("{}{}", cfg.wrappropertybase, ident),
+                    parts![&cfg.wrappropertybase, ident],
                 ));
             }
             R::RebindProperty { ident, tempvar } => {
                 let target = if tempvar {
-                    cfg.templocid.clone()
+                    TransformElement::from(&cfg.templocid)
                 } else {
-                    ident.clone()
+                    TransformElement::from(ident.clone())
                 };
-                out.push(JsChange::replace_owned(
-                    self.span,
-                    format!("{}{}: {}", cfg.wrappropertybase, ident, target),
-                ));
+                let mut text = parts![&cfg.wrappropertybase, ident, ": "];
+                text.push(target);
+                out.push(JsChange::replace(self.span, text));
             }
             R::TempVar => {
-                out.push(JsChange::replace_owned(self.span, cfg.templocid.clone()));
+                out.push(JsChange::replace(self.span, parts![&cfg.templocid]));
             }
             R::WrapObjectAssignment {
                 restids,
                 location_assigned,
             } => {
-                let rest = restids
-                    .iter()
-                    .map(|id| format!("{}({})", cfg.cleanrestfn, id))
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                let loc = if location_assigned {
-                    format!(", {}(location, \"=\", t)||(location=t)", cfg.trysetfn)
-                } else {
-                    String::new()
-                };
-                let prefix = format!("((t)=>({}{}))(", rest, loc);
-                out.push(JsChange::insert_left_owned(self.span, prefix));
-                out.push(JsChange::insert_right(self.span, ")".to_string()));
+                let mut prefix = parts!["((t)=>("];
+                for (i, id) in restids.into_iter().enumerate() {
+                    if i > 0 {
+                        prefix.push(", ".into());
+                    }
+                    prefix.extend(parts![&cfg.cleanrestfn, "(", id, ")"]);
+                }
+                if location_assigned {
+                    prefix.extend(parts![
+                        ", ",
+                        &cfg.trysetfn,
+                        "(location, \"=\", t)||(location=t)"
+                    ]);
+                }
+                prefix.push("))(".into());
+                out.push(JsChange::insert_left(self.span, prefix));
+                out.push(JsChange::insert_right(self.span, parts![")"]));
             }
             R::WrapProperty => {
-                out.push(JsChange::insert_left_owned(
+                out.push(JsChange::insert_left(
                     self.span,
-                    format!("{}(", cfg.wrappropertyfn),
+                    parts![&cfg.wrappropertyfn, "("],
                 ));
-                out.push(JsChange::insert_right(self.span, ")".to_string()));
+                out.push(JsChange::insert_right(self.span, parts![")"]));
             }
             R::RascalErr { ident } => {
-                out.push(JsChange::rascal_err(self.span, format!("$rascalerr({ident});")));
+                out.push(JsChange::rascal_err(
+                    self.span,
+                    parts!["$rascalerr(", ident, ");"],
+                ));
             }
             R::Rascalitize => {
-                out.push(JsChange::insert_left_owned(self.span, "$rascalitize(".to_string()));
-                out.push(JsChange::insert_right(self.span, ")".to_string()));
+                out.push(JsChange::insert_left(self.span, parts!["$rascalitize("]));
+                out.push(JsChange::insert_right(self.span, parts![")"]));
             }
             R::Eval { inner } => {
-                out.push(JsChange::insert_left_owned(
-                    inner,
-                    format!("{}(", cfg.rewritefn),
-                ));
-                out.push(JsChange::insert_right(inner, ")".to_string()));
+                out.push(JsChange::insert_left(inner, parts![&cfg.rewritefn, "("]));
+                out.push(JsChange::insert_right(inner, parts![")"]));
             }
             R::Assignment {
                 name,
                 rhs_text,
                 op: _,
             } => {
-                out.push(JsChange::replace_owned(
+                out.push(JsChange::replace(
                     self.span,
-                    format!(
-                        "((t)=>{}({},\"=\",t)||({}=t))({})",
-                        cfg.trysetfn, name, name, rhs_text
-                    ),
+                    parts![
+                        "((t)=>",
+                        &cfg.trysetfn,
+                        "(",
+                        name.clone(),
+                        ",\"=\",t)||(",
+                        name,
+                        "=t))(",
+                        rhs_text,
+                        ")"
+                    ],
                 ));
             }
             R::ShorthandObj { name } => {
-                out.push(JsChange::replace_owned(
+                out.push(JsChange::replace(
                     self.span,
-                    format!("{}: {}({})", name, cfg.wrapfn, name),
+                    parts![name.clone(), ": ", &cfg.wrapfn, "(", name, ")"],
                 ));
             }
             R::SourceTag => {
-                out.push(JsChange::insert_left_owned(
+                out.push(JsChange::insert_left(
                     self.span,
-                    format!("/*rascaltag {} {}*/", self.span.start, cfg.prefix),
+                    parts!["/*rascaltag ", self.span.start, " ", &flags.sourcetag, "*/"],
+                ));
+            }
+            R::SourceUrl { url } => {
+                out.push(JsChange::insert_after(
+                    self.span,
+                    parts!["\n//# sourceURL=", url],
                 ));
             }
             R::CleanFunction {
@@ -178,48 +252,59 @@ impl Rewrite {
                 location_assigned,
                 wrap,
             } => {
-                let mut body = String::new();
+                let (left, right) = if expression {
+                    ("(", Some(")"))
+                } else if wrap {
+                    ("{", Some("}"))
+                } else {
+                    (";", None)
+                };
+                let mut body = parts![left];
                 for id in restids {
-                    body.push_str(&format!("{}({});", cfg.cleanrestfn, id));
+                    body.extend(parts![&cfg.cleanrestfn, "(", id, ");"]);
                 }
                 if location_assigned {
-                    body.push_str(&format!(
-                        "{}(location,\"=\",{})||(location={});",
-                        cfg.trysetfn, cfg.templocid, cfg.templocid
-                    ));
+                    body.extend(location_reset(cfg, ";"));
                 }
                 if expression {
-                    out.push(JsChange::insert_left_owned(self.span, format!("({},", body)));
-                    out.push(JsChange::insert_right(self.span, ")".to_string()));
-                } else if wrap {
-                    out.push(JsChange::insert_left_owned(self.span, format!("{{{}", body)));
-                    out.push(JsChange::insert_right(self.span, "}".to_string()));
-                } else {
-                    out.push(JsChange::insert_left_owned(self.span, format!(";{}", body)));
+                    body.push(",".into());
+                }
+                out.push(JsChange::insert_left(self.span, body));
+                if let Some(right) = right {
+                    out.push(JsChange::insert_right(self.span, parts![right]));
                 }
             }
             R::CleanVariableDeclaration {
                 restids,
                 location_assigned,
             } => {
-                let mut suffix = String::new();
+                let mut suffix = parts![", ", &cfg.tempunusedid, " = ("];
                 for id in restids {
-                    suffix.push_str(&format!("{}({}),", cfg.cleanrestfn, id));
+                    suffix.extend(parts![&cfg.cleanrestfn, "(", id, "),"]);
                 }
                 if location_assigned {
-                    suffix.push_str(&format!(
-                        "{}(location,\"=\",{})||(location={}),",
-                        cfg.trysetfn, cfg.templocid, cfg.templocid
-                    ));
+                    suffix.extend(location_reset(cfg, ","));
                 }
-                out.push(JsChange::insert_right_owned(
-                    self.span,
-                    format!(", {} = ({}, 0)", cfg.tempunusedid, suffix),
-                ));
+                suffix.push(", 0)".into());
+                out.push(JsChange::insert_after(self.span, suffix));
             }
-            R::Replace { text } => out.push(JsChange::replace_owned(self.span, text)),
-            R::Delete => out.push(JsChange::replace_owned(self.span, String::new())),
+            R::Replace { text } => out.push(JsChange::replace(self.span, parts![text])),
+            R::Inject { text } => out.push(JsChange::inject(self.span, parts![text])),
+            R::Delete => out.push(JsChange::replace(self.span, Parts::new())),
         }
         out
     }
-}
\ No newline at end of file
+}
+
+/// Puts back a `location` assignment that went through `Config::templocid`.
+fn location_reset<'a>(cfg: &'a Config, end: &'a str) -> Parts<'a> {
+    parts![
+        &cfg.trysetfn,
+        "(location,\"=\",",
+        &cfg.templocid,
+        ")||(location=",
+        &cfg.templocid,
+        ")",
+        end
+    ]
+}
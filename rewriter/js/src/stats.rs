@@ -0,0 +1,101 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use oxc::span::Span;
+
+use crate::rewrite::Rewrite;
+
+/// Reads a monotonic clock. `std::time::Instant` panics on
+/// `wasm32-unknown-unknown`, so embedders there supply their own through
+/// [`crate::Rewriter::set_clock`].
+pub type Clock = fn() -> Duration;
+
+/// Whether [`default_clock`] tells the time. On wasm32 it always reads zero,
+/// which would leave timings empty and time budgets that never run out.
+pub const HAS_DEFAULT_CLOCK: bool = !cfg!(target_arch = "wasm32");
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn default_clock() -> Duration {
+    use std::{sync::OnceLock, time::Instant};
+
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn default_clock() -> Duration {
+    Duration::ZERO
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RewriteStats {
+    /// Rewrites by [`crate::rewrite::RewriteType::name`].
+    pub counts: BTreeMap<&'static str, u32>,
+    /// Deciding whether the script has to be parsed at all.
+    pub prefilter: Duration,
+    pub parse: Duration,
+    /// The visitor, passes, patch rules and init scripts.
+    pub visit: Duration,
+    /// Applying the changes and building the sourcemap.
+    pub transform: Duration,
+    pub input_size: usize,
+    pub output_size: usize,
+}
+
+/// Where one rewrite came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub kind: &'static str,
+    /// Span in the input.
+    pub span: Span,
+    /// The `visit_*` method of the built-in visitor, the name of a pass,
+    /// `patch:<rule>` or `init:<script>`.
+    pub source: String,
+}
+
+/// Remembers which producer added each rewrite, kept in step with the
+/// rewrites.
+#[derive(Debug, Default)]
+pub(crate) struct Origins {
+    sources: Vec<String>,
+    /// Index into `sources` for each rewrite.
+    of: Vec<u32>,
+}
+
+impl Origins {
+    /// Everything added up to `end` that isn't marked yet came from `source`.
+    pub fn mark(&mut self, end: usize, source: impl Into<String>) {
+        if end > self.of.len() {
+            let idx = self.sources.len() as u32;
+            self.sources.push(source.into());
+            self.of.resize(end, idx);
+        }
+    }
+
+    /// Drops the rewrites `keep` says no to, along with their origins.
+    pub fn retain(&mut self, rewrites: &mut Vec<Rewrite>, keep: &[bool]) {
+        let mut keep_iter = keep.iter();
+        rewrites.retain(|_| *keep_iter.next().unwrap_or(&true));
+        let mut keep_iter = keep.iter();
+        self.of.retain(|_| *keep_iter.next().unwrap_or(&true));
+    }
+
+    /// Whether any rewrite came from `source`.
+    pub fn contains(&self, source: &str) -> bool {
+        self.sources
+            .iter()
+            .enumerate()
+            .any(|(i, s)| s == source && self.of.contains(&(i as u32)))
+    }
+
+    pub fn provenance(&self, rewrites: &[Rewrite]) -> Vec<Provenance> {
+        rewrites
+            .iter()
+            .zip(&self.of)
+            .map(|(rewrite, &source)| Provenance {
+                kind: rewrite.ty.name(),
+                span: rewrite.span,
+                source: self.sources[source as usize].clone(),
+            })
+            .collect()
+    }
+}
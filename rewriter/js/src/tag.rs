@@ -0,0 +1,160 @@
+use crate::{
+    cfg::{Config, Flags},
+    init::InitScript,
+    patch::{PatchAction, PatchRule, PatchTarget},
+};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a, which is fast on short inputs and, unlike the std hasher, stable
+/// across builds, so tags can be cached.
+struct Fnv(u64);
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    /// Length-prefixed so that adjacent fields can't run into each other.
+    fn field(&mut self, text: &str) {
+        self.write(&(text.len() as u64).to_le_bytes());
+        self.write(text.as_bytes());
+    }
+
+    fn optional(&mut self, text: Option<&str>) {
+        match text {
+            Some(text) => {
+                self.write(&[1]);
+                self.field(text);
+            }
+            None => self.write(&[0]),
+        }
+    }
+}
+
+/// Derives a source tag from everything that affects the rewrite, so equal
+/// inputs get equal tags and outputs: the input, the config, the flags, the
+/// names of the custom passes and the patch rules and init scripts. A pass is
+/// only known by its name, so one that changes what it does should change
+/// its name too. `Flags::tag_salt` is mixed in for callers that need distinct
+/// tags for equal inputs.
+pub fn source_tag<'a>(
+    js: &str,
+    cfg: &Config,
+    flags: &Flags,
+    passes: impl IntoIterator<Item = &'a str>,
+    rules: &[PatchRule],
+    init_scripts: &[InitScript],
+) -> String {
+    let mut h = Fnv(FNV_OFFSET);
+    h.field(js);
+
+    let Config {
+        prefix,
+        wrapfn,
+        wrappropertybase,
+        wrappropertyfn,
+        cleanrestfn,
+        importfn,
+        rewritefn,
+        setrealmfn,
+        metafn,
+        pushsourcemapfn,
+        trysetfn,
+        templocid,
+        tempunusedid,
+        unsafe_globals,
+        unsafe_properties,
+    } = cfg;
+    for field in [
+        prefix,
+        wrapfn,
+        wrappropertybase,
+        wrappropertyfn,
+        cleanrestfn,
+        importfn,
+        rewritefn,
+        setrealmfn,
+        metafn,
+        pushsourcemapfn,
+        trysetfn,
+        templocid,
+        tempunusedid,
+    ] {
+        h.field(field);
+    }
+    for list in [unsafe_globals, unsafe_properties] {
+        h.write(&(list.len() as u64).to_le_bytes());
+        for name in list {
+            h.field(name);
+        }
+    }
+
+    h.field(&flags.base);
+    h.field(&flags.url);
+    h.field(&flags.tag_salt);
+    h.write(&[
+        flags.is_module as u8,
+        flags.capture_errors as u8,
+        flags.rascalitize as u8,
+        flags.do_sourcemaps as u8,
+        flags.inline_sourcemaps as u8,
+        flags.strict_rewrites as u8,
+        flags.destructure_rewrites as u8,
+        flags.invalid_js as u8,
+    ]);
+
+    for pass in passes {
+        h.field(pass);
+    }
+    h.write(&(rules.len() as u64).to_le_bytes());
+    for rule in rules {
+        let PatchRule {
+            name,
+            url,
+            host,
+            target,
+            action,
+            text,
+        } = rule;
+        h.field(name);
+        h.optional(url.as_deref());
+        h.optional(host.as_deref());
+        let (target, on) = match target {
+            PatchTarget::Snippet(on) => (0, on),
+            PatchTarget::Call(on) => (1, on),
+            PatchTarget::Member(on) => (2, on),
+            PatchTarget::Identifier(on) => (3, on),
+        };
+        let action = match action {
+            PatchAction::Replace => 0,
+            PatchAction::InsertBefore => 1,
+            PatchAction::InsertAfter => 2,
+            PatchAction::Delete => 3,
+        };
+        h.write(&[target, action]);
+        h.field(on);
+        h.field(text);
+    }
+    h.write(&(init_scripts.len() as u64).to_le_bytes());
+    for script in init_scripts {
+        let InitScript {
+            name,
+            url,
+            host,
+            kind,
+            code,
+        } = script;
+        h.field(name);
+        h.optional(url.as_deref());
+        h.optional(host.as_deref());
+        h.write(&[*kind as u8]);
+        h.field(code);
+    }
+
+    format!("tag-{:016x}", h.0)
+}
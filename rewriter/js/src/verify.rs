@@ -0,0 +1,113 @@
+use oxc::{
+    allocator::Allocator,
+    ast::{AstKind, ast::Expression},
+    parser::Parser,
+    semantic::{Semantic, SemanticBuilder},
+    span::{GetSpan, SourceType, Span},
+};
+use transform::OriginalPositions;
+
+use crate::{
+    cfg::{Config, Flags},
+    diagnostic::{Diagnostic, LineIndex, Severity, VERIFY_LEAK, VERIFY_SYNTAX},
+    injection::strip_bom,
+};
+
+/// Re-parses rewritten output and reports what the rewrite broke: syntax
+/// errors the input didn't have, and unsafe globals or properties that were
+/// left unwrapped outside injected code. An error counts as one the input had
+/// when `input_errors` has one with the same message where `positions` maps
+/// it back to. Spans point into `output`.
+pub fn verify(
+    output: &str,
+    input_errors: &[Diagnostic],
+    positions: &OriginalPositions,
+    cfg: &Config,
+    flags: &Flags,
+) -> Vec<Diagnostic> {
+    let alloc = Allocator::default();
+    let body = strip_bom(output);
+    let base = (output.len() - body.len()) as u32;
+    let source_type = if flags.is_module.is_module() {
+        SourceType::mjs()
+    } else {
+        SourceType::script()
+    };
+    let parsed = Parser::new(&alloc, body, source_type).parse();
+    let lines = LineIndex::new(output);
+
+    let mut out = Vec::new();
+    let had = |diag: &Diagnostic| {
+        let at = diag
+            .span
+            .as_ref()
+            .map(|s| positions.original(s.start.offset));
+        input_errors
+            .iter()
+            .any(|e| e.message == diag.message && e.span.as_ref().map(|s| s.start.offset) == at)
+    };
+    for err in parsed.errors {
+        let mut diag = Diagnostic::from_oxc(err, &lines, base, &flags.url);
+        if had(&diag) {
+            continue;
+        }
+        diag.message = format!("rewritten output does not parse: {}", diag.message);
+        diag.code = VERIFY_SYNTAX.to_string();
+        out.push(diag);
+    }
+    if !out.is_empty() {
+        return out;
+    }
+
+    let semantic = SemanticBuilder::new().build(&parsed.program).semantic;
+    for node in semantic.nodes().iter() {
+        let leak = match node.kind() {
+            AstKind::IdentifierReference(ident)
+                if cfg.is_unsafe_global(&ident.name)
+                    && is_global(&semantic, ident.reference_id.get())
+                    && !is_wrapped(&semantic, node.id(), ident.span, cfg) =>
+            {
+                Some((ident.name.as_str(), ident.span))
+            }
+            AstKind::StaticMemberExpression(member)
+                if cfg.is_unsafe_property(&member.property.name) =>
+            {
+                Some((member.property.name.as_str(), member.property.span))
+            }
+            _ => None,
+        };
+        if let Some((name, span)) = leak
+            && !positions.injected(base + span.start)
+        {
+            out.push(Diagnostic::at(
+                Severity::Error,
+                VERIFY_LEAK,
+                format!("`{name}` is not wrapped in the rewritten output"),
+                &lines,
+                Span::new(base + span.start, base + span.end),
+                &flags.url,
+            ));
+        }
+    }
+    out
+}
+
+pub(crate) fn is_global(
+    semantic: &Semantic<'_>,
+    reference: Option<oxc::semantic::ReferenceId>,
+) -> bool {
+    reference.is_some_and(|id| semantic.scoping().get_reference(id).symbol_id().is_none())
+}
+
+fn is_wrapped(
+    semantic: &Semantic<'_>,
+    node: oxc::semantic::NodeId,
+    span: Span,
+    cfg: &Config,
+) -> bool {
+    let AstKind::CallExpression(call) = semantic.nodes().parent_kind(node) else {
+        return false;
+    };
+    matches!(&call.callee, Expression::Identifier(callee) if callee.name == cfg.wrapfn.as_str())
+        && call.arguments.iter().any(|arg| arg.span() == span)
+}
@@ -1,12 +1,22 @@
-use oxc::span::Span;
+use std::borrow::Cow;
+
+use oxc::{
+    ast::ast::{
+        Argument, BindingIdentifier, CallExpression, Expression, NewExpression, ObjectPropertyKind,
+        Program, PropertyKey,
+    },
+    ast_visit::{Visit, walk},
+    span::{GetSpan, Span},
+};
 
 use crate::{
-    cfg::{Config, Flags, UrlRewriter},
+    budget::Deadline,
+    cfg::{Config, Flags, UrlDestination, UrlRewriter},
+    injection::InjectionPoints,
     rewrite::{Rewrite, RewriteType},
+    stats::Origins,
 };
 
-pub const UNSAFE_GLOBALS: &[&str] = &["parent", "top", "location", "eval"];
-
 #[derive(Debug)]
 struct Token<'a> {
     text: &'a str,
@@ -20,30 +30,73 @@ struct Token<'a> {
 #[derive(Debug)]
 pub struct JsVisitor<'data, E: UrlRewriter> {
     src: &'data str,
+    program: &'data Program<'data>,
     cfg: &'data Config,
     flags: &'data Flags,
-    _url: &'data E,
+    url: &'data E,
+    points: InjectionPoints,
+    /// Checked as the loops go; when it runs out they stop early and the
+    /// caller throws the rewrites away.
+    deadline: &'data Deadline,
     rewrites: Vec<Rewrite>,
 }
 
 impl<'data, E: UrlRewriter> JsVisitor<'data, E> {
-    pub fn new(src: &'data str, cfg: &'data Config, flags: &'data Flags, url: &'data E) -> Self {
+    pub fn new(
+        src: &'data str,
+        program: &'data Program<'data>,
+        cfg: &'data Config,
+        flags: &'data Flags,
+        url: &'data E,
+        points: InjectionPoints,
+        deadline: &'data Deadline,
+    ) -> Self {
         Self {
             src,
+            program,
             cfg,
             flags,
-            _url: url,
+            url,
+            points,
+            deadline,
             rewrites: Vec::new(),
         }
     }
 
-    pub fn run(mut self) -> Vec<Rewrite> {
-        self.visit_identifier_reference();
-        self.visit_member_expression();
-        self.visit_import_expression();
-        self.visit_meta_property();
-        self.visit_debugger_statement();
-        self.visit_function_body();
+    pub fn run(self) -> Vec<Rewrite> {
+        self.run_traced(&mut Origins::default())
+    }
+
+    /// [`Self::run`], marking in `origins` which method made each rewrite.
+    pub(crate) fn run_traced(mut self, origins: &mut Origins) -> Vec<Rewrite> {
+        macro_rules! visit {
+            ($($method:ident),* $(,)?) => {
+                $(
+                    self.$method();
+                    origins.mark(self.rewrites.len(), stringify!($method));
+                )*
+            };
+        }
+        // First, so the tag goes ahead of anything else inserted at its offset.
+        visit!(
+            visit_function_body,
+            visit_identifier_reference,
+            visit_member_expression,
+            visit_import_expression,
+            visit_meta_property,
+            visit_debugger_statement,
+            visit_url_arguments,
+            visit_source_url,
+        );
+
+        // The BOM and the hashbang line are not code.
+        let start = self.points.comment;
+        let keep = self
+            .rewrites
+            .iter()
+            .map(|r| r.span.start >= start)
+            .collect::<Vec<_>>();
+        origins.retain(&mut self.rewrites, &keep);
         self.rewrites
     }
 
@@ -54,6 +107,9 @@ impl<'data, E: UrlRewriter> JsVisitor<'data, E> {
         let mut prev_token: Option<&'data str> = None;
 
         while i < bytes.len() {
+            if self.deadline.tick() {
+                break;
+            }
             let c = bytes[i] as char;
             if c == '"' || c == '\'' || c == '`' {
                 let quote = c;
@@ -127,7 +183,7 @@ impl<'data, E: UrlRewriter> JsVisitor<'data, E> {
 
     pub fn visit_identifier_reference(&mut self) {
         for tok in self.tokenize() {
-            if !UNSAFE_GLOBALS.contains(&tok.text) {
+            if !self.cfg.is_unsafe_global(tok.text) {
                 continue;
             }
             let is_decl_context = matches!(
@@ -159,7 +215,7 @@ impl<'data, E: UrlRewriter> JsVisitor<'data, E> {
             if tok.prev_non_ws != Some('.') {
                 continue;
             }
-            if UNSAFE_GLOBALS.contains(&tok.text) {
+            if self.cfg.is_unsafe_property(tok.text) {
                 self.rewrites.push(Rewrite {
                     span: Span::new(tok.start, tok.end),
                     ty: RewriteType::RewriteProperty {
@@ -178,6 +234,9 @@ impl<'data, E: UrlRewriter> JsVisitor<'data, E> {
         let bytes = self.src.as_bytes();
         while i < bytes.len() {
             if bytes[i] as char == '[' {
+                if self.deadline.tick() {
+                    return;
+                }
                 let prev = find_prev_non_ws(self.src, i);
                 let should_wrap = matches!(prev, Some('.' | ')' | ']'))
                     || prev.map(is_ident_continue).unwrap_or(false)
@@ -190,6 +249,9 @@ impl<'data, E: UrlRewriter> JsVisitor<'data, E> {
                 let mut depth = 1usize;
                 i += 1;
                 while i < bytes.len() && depth > 0 {
+                    if self.deadline.tick() {
+                        return;
+                    }
                     let ch = bytes[i] as char;
                     if ch == '[' {
                         depth += 1;
@@ -227,6 +289,9 @@ impl<'data, E: UrlRewriter> JsVisitor<'data, E> {
     pub fn visit_meta_property(&mut self) {
         let mut from = 0usize;
         while let Some(rel) = self.src[from..].find("import.meta") {
+            if self.deadline.tick() {
+                return;
+            }
             let start = from + rel;
             let end = start + "import.meta".len();
             self.rewrites.push(Rewrite {
@@ -240,6 +305,9 @@ impl<'data, E: UrlRewriter> JsVisitor<'data, E> {
     pub fn visit_debugger_statement(&mut self) {
         let mut from = 0usize;
         while let Some(rel) = self.src[from..].find("debugger") {
+            if self.deadline.tick() {
+                return;
+            }
             let start = from + rel;
             let mut end = start + "debugger".len();
             while end < self.src.len() {
@@ -262,11 +330,74 @@ impl<'data, E: UrlRewriter> JsVisitor<'data, E> {
     }
 
     pub fn visit_function_body(&mut self) {
-        if self.flags.do_sourcemaps {
+        source_tag(self.flags, self.points, &mut self.rewrites);
+    }
+
+    /// Names the script after the URL it was loaded from, replacing the value
+    /// of an existing `sourceURL` comment so there is only one.
+    pub fn visit_source_url(&mut self) {
+        if self.flags.url.is_empty() {
+            return;
+        }
+        let url = escape_comment_url(&self.flags.url);
+
+        let existing = self.program.comments.iter().rev().find(|c| {
+            let text = c.content_span().source_text(self.program.source_text);
+            c.is_line() && (text.starts_with("# sourceURL=") || text.starts_with("@ sourceURL="))
+        });
+        if let Some(comment) = existing {
+            let value = comment.content_span();
+            let start = self.points.bom + value.start + "# sourceURL=".len() as u32;
             self.rewrites.push(Rewrite {
-                span: Span::new(0, 0),
-                ty: RewriteType::SourceTag,
+                span: Span::new(start, self.points.bom + value.end),
+                ty: RewriteType::Replace { text: url },
             });
+        } else {
+            append_source_url(self.src, url, &mut self.rewrites);
+        }
+    }
+
+    /// Rewrites literal script URLs, and `new URL(literal, base)` with a
+    /// literal or `import.meta.url` base, passed to worker constructors,
+    /// `serviceWorker.register`, worklet `addModule` and `importScripts`.
+    /// Anything else is left to the runtime hooks.
+    pub fn visit_url_arguments(&mut self) {
+        let mut finder = UrlArguments::default();
+        finder.visit_program(self.program);
+        for site in finder.finish() {
+            let flags = match site.base {
+                None => Cow::Borrowed(self.flags),
+                Some(base) => Cow::Owned(Flags {
+                    base: match base {
+                        UrlBase::Literal(base) => base,
+                        UrlBase::Script if self.flags.url.is_empty() => self.flags.base.clone(),
+                        UrlBase::Script => self.flags.url.clone(),
+                    },
+                    ..self.flags.clone()
+                }),
+            };
+            let mut text = String::new();
+            let rewritten = self.url.rewrite(
+                self.cfg,
+                &flags,
+                &site.url,
+                &mut text,
+                site.dest,
+                site.module,
+            );
+            if rewritten.is_err() {
+                continue;
+            }
+            let Ok(quoted) = serde_json::to_string(&text) else {
+                continue;
+            };
+            let bom = self.points.bom;
+            self.rewrite_url(
+                bom + site.span.start,
+                bom + site.span.end,
+                quoted,
+                site.module,
+            );
         }
     }
 
@@ -317,6 +448,262 @@ impl<'data, E: UrlRewriter> JsVisitor<'data, E> {
     }
 }
 
+/// What a URL in a `new URL(url, base)` argument is relative to.
+enum UrlBase {
+    Literal(String),
+    /// `import.meta.url`.
+    Script,
+}
+
+struct UrlSite {
+    /// The argument, which gets replaced with a string literal.
+    span: Span,
+    url: String,
+    base: Option<UrlBase>,
+    dest: UrlDestination,
+    module: bool,
+}
+
+/// Finds the URL arguments [`JsVisitor::visit_url_arguments`] rewrites.
+#[derive(Default)]
+struct UrlArguments {
+    sites: Vec<UrlSite>,
+    /// Those of bare `importScripts` calls, which are the worker global's
+    /// unless the script declares its own.
+    bare_import_scripts: Vec<UrlSite>,
+    declares_import_scripts: bool,
+}
+
+impl UrlArguments {
+    fn finish(mut self) -> Vec<UrlSite> {
+        if !self.declares_import_scripts {
+            self.sites.append(&mut self.bare_import_scripts);
+        }
+        self.sites
+    }
+
+    fn add(&mut self, arg: &Argument<'_>, dest: UrlDestination, module: bool) {
+        if let Some(site) = url_site(arg, dest, module) {
+            self.sites.push(site);
+        }
+    }
+}
+
+/// The site of a literal URL argument, or of `new URL(literal, base)`.
+fn url_site(arg: &Argument<'_>, dest: UrlDestination, module: bool) -> Option<UrlSite> {
+    let expr = arg.as_expression()?;
+    let (url, base, span) = match expr.without_parentheses() {
+        Expression::NewExpression(new)
+            if matches!(&new.callee, Expression::Identifier(c) if c.name == "URL")
+                && new.arguments.len() == 2 =>
+        {
+            let url = new.arguments[0].as_expression().and_then(literal_string)?;
+            let base = match new.arguments[1]
+                .as_expression()
+                .map(|e| e.without_parentheses())
+            {
+                Some(Expression::StaticMemberExpression(m))
+                    if m.property.name == "url"
+                        && matches!(&m.object, Expression::MetaProperty(meta)
+                                if meta.meta.name == "import" && meta.property.name == "meta") =>
+                {
+                    UrlBase::Script
+                }
+                Some(base) => UrlBase::Literal(literal_string(base)?.to_string()),
+                None => return None,
+            };
+            (url, Some(base), new.span)
+        }
+        expr => (literal_string(expr)?, None, expr.span()),
+    };
+    Some(UrlSite {
+        span,
+        url: url.to_string(),
+        base,
+        dest,
+        module,
+    })
+}
+
+impl<'a> Visit<'a> for UrlArguments {
+    fn visit_new_expression(&mut self, it: &NewExpression<'a>) {
+        let dest = match callee_name(&it.callee) {
+            Some("Worker") => Some(UrlDestination::Worker),
+            Some("SharedWorker") => Some(UrlDestination::SharedWorker),
+            _ => None,
+        };
+        if let Some(dest) = dest
+            && let Some(module) = module_option(it.arguments.get(1))
+            && let Some(url) = it.arguments.first()
+        {
+            self.add(url, dest, module);
+        }
+        walk::walk_new_expression(self, it);
+    }
+
+    fn visit_call_expression(&mut self, it: &CallExpression<'a>) {
+        let object = it
+            .callee
+            .as_member_expression()
+            .and_then(|m| callee_name(m.object()));
+        match (callee_name(&it.callee), object) {
+            (Some("register"), Some("serviceWorker")) => {
+                if let Some(module) = module_option(it.arguments.get(1))
+                    && let Some(url) = it.arguments.first()
+                {
+                    self.add(url, UrlDestination::ServiceWorker, module);
+                }
+            }
+            (Some("addModule"), Some(worklet @ ("paintWorklet" | "audioWorklet"))) => {
+                let dest = if worklet == "paintWorklet" {
+                    UrlDestination::PaintWorklet
+                } else {
+                    UrlDestination::AudioWorklet
+                };
+                if let Some(url) = it.arguments.first() {
+                    self.add(url, dest, true);
+                }
+            }
+            _ => {}
+        }
+        if let Some(bare) = import_scripts_callee(&it.callee) {
+            for url in &it.arguments {
+                if let Some(site) = url_site(url, UrlDestination::Script, false) {
+                    if bare {
+                        self.bare_import_scripts.push(site);
+                    } else {
+                        self.sites.push(site);
+                    }
+                }
+            }
+        }
+        walk::walk_call_expression(self, it);
+    }
+
+    fn visit_binding_identifier(&mut self, it: &BindingIdentifier<'a>) {
+        if it.name == "importScripts" {
+            self.declares_import_scripts = true;
+        }
+    }
+}
+
+/// Whether `callee` is the worker global's `importScripts`: `Some(true)` for a
+/// bare `importScripts`, `Some(false)` for one reached through `self` or
+/// `globalThis`.
+fn import_scripts_callee(callee: &Expression<'_>) -> Option<bool> {
+    match callee.without_parentheses() {
+        Expression::Identifier(ident) => (ident.name == "importScripts").then_some(true),
+        expr => {
+            let member = expr.as_member_expression()?;
+            let global = matches!(member.object().without_parentheses(),
+                Expression::Identifier(object) if object.name == "self" || object.name == "globalThis");
+            (global && member.static_property_name()? == "importScripts").then_some(false)
+        }
+    }
+}
+
+/// `name` or `anything.name`.
+fn callee_name<'a>(expr: &'a Expression<'a>) -> Option<&'a str> {
+    match expr.without_parentheses() {
+        Expression::Identifier(ident) => Some(ident.name.as_str()),
+        expr => expr.as_member_expression()?.static_property_name(),
+    }
+}
+
+fn literal_string<'a>(expr: &'a Expression<'a>) -> Option<&'a str> {
+    match expr.without_parentheses() {
+        Expression::StringLiteral(lit) => Some(lit.value.as_str()),
+        Expression::TemplateLiteral(t) if t.expressions.is_empty() => {
+            t.quasis.first()?.value.cooked.as_ref().map(|c| c.as_str())
+        }
+        _ => None,
+    }
+}
+
+/// Whether worker options ask for a module: `None` when they can't be read
+/// statically.
+fn module_option(options: Option<&Argument<'_>>) -> Option<bool> {
+    let Some(options) = options else {
+        return Some(false);
+    };
+    let Some(Expression::ObjectExpression(object)) = options.as_expression() else {
+        return None;
+    };
+    let mut module = false;
+    for property in &object.properties {
+        let ObjectPropertyKind::ObjectProperty(property) = property else {
+            return None;
+        };
+        let is_type = match &property.key {
+            PropertyKey::StaticIdentifier(key) => key.name == "type",
+            PropertyKey::StringLiteral(key) => key.value == "type",
+            _ if property.computed => return None,
+            _ => false,
+        };
+        if is_type {
+            module = literal_string(&property.value)? == "module";
+        }
+    }
+    Some(module)
+}
+
+/// What the visitor adds to a script with nothing to rewrite and no
+/// `sourceURL` comment, so those can be handled without a parse. Marks them
+/// in `origins` as the methods that add them to a parsed script.
+pub(crate) fn unparsed_rewrites(
+    src: &str,
+    flags: &Flags,
+    points: InjectionPoints,
+    origins: &mut Origins,
+) -> Vec<Rewrite> {
+    let mut out = Vec::new();
+    source_tag(flags, points, &mut out);
+    origins.mark(out.len(), "visit_function_body");
+    if !flags.url.is_empty() {
+        append_source_url(src, escape_comment_url(&flags.url), &mut out);
+    }
+    origins.mark(out.len(), "visit_source_url");
+    out
+}
+
+fn source_tag(flags: &Flags, points: InjectionPoints, out: &mut Vec<Rewrite>) {
+    if flags.do_sourcemaps {
+        let at = Span::new(points.comment, points.comment);
+        if points.comment_newline {
+            out.push(Rewrite {
+                span: at,
+                ty: RewriteType::Replace { text: "\n".into() },
+            });
+        }
+        out.push(Rewrite {
+            span: at,
+            ty: RewriteType::SourceTag,
+        });
+    }
+}
+
+fn append_source_url(src: &str, url: String, out: &mut Vec<Rewrite>) {
+    let end = src.len() as u32;
+    out.push(Rewrite {
+        span: Span::new(end, end),
+        ty: RewriteType::SourceUrl { url },
+    });
+}
+
+/// Whitespace would end the comment's value early, and a line break would end
+/// the comment itself.
+fn escape_comment_url(url: &str) -> String {
+    let mut out = String::with_capacity(url.len());
+    for c in url.chars() {
+        if c.is_ascii_whitespace() {
+            out.push_str(&format!("%{:02X}", c as u32));
+        } else if !c.is_whitespace() {
+            out.push(c);
+        }
+    }
+    out
+}
+
 fn is_ident_start(c: char) -> bool {
     c == '_' || c == '$' || c.is_ascii_alphabetic()
 }
@@ -379,7 +766,10 @@ fn previous_words_before(src: &str, mut idx: usize) -> Vec<&str> {
                 break;
             }
         }
-        if idx == 0 && !((src.as_bytes()[0] as char).is_ascii_alphanumeric() || src.as_bytes()[0] as char == '_') {
+        if idx == 0
+            && !((src.as_bytes()[0] as char).is_ascii_alphanumeric()
+                || src.as_bytes()[0] as char == '_')
+        {
             break;
         }
 
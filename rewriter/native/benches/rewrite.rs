@@ -0,0 +1,129 @@
+//! Times rewriting a bundle with and without the prefilter, and counts the
+//! allocations each rewrite makes:
+//!
+//! ```sh
+//! cargo bench -p native --bench rewrite [-- path/to/bundle.js ...]
+//! ```
+//!
+//! Without arguments it uses generated bundles: one the prefilter clears, one
+//! it can't, and one where most lines are rewritten.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use js::cfg::Flags;
+use native::rewriter::NativeRewriter;
+
+struct Counting;
+
+static ALLOCS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+#[allow(unsafe_code)]
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Minified-library style code with nothing for the rewriter to do.
+const CLEAN: &str = "function n(t,e){for(var r=0,o=t.length;r<o;r++)if(e(t.charAt(r),r))return r;return-1}\
+var u=function(t){return t.replace(/\\s+/g,\" \").trim()},c={version:\"1.0.0\",each:n,trim:u};\
+Object.defineProperty(c,\"size\",{get:function(){return Object.keys(this).length}});\n";
+
+/// The same with a window lookup, which needs rewriting.
+const DIRTY: &str = "var w=typeof window!==\"undefined\"?window.top:null;\n";
+
+/// Code where most lines need rewriting.
+const BUSY: &str =
+    "for(var i=0;i<n.length;i++)r[i]=n[i]||top.location.href;parent.postMessage(r,\"*\");\n";
+
+fn main() {
+    let paths = std::env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with('-'))
+        .collect::<Vec<_>>();
+    let inputs = if paths.is_empty() {
+        let clean = CLEAN.repeat(2 << 20 >> 8);
+        let dirty = format!("{DIRTY}{clean}");
+        let busy = BUSY.repeat(2 << 20 >> 7);
+        vec![
+            ("generated clean".to_string(), clean),
+            ("generated dirty".to_string(), dirty),
+            ("generated busy".to_string(), busy),
+        ]
+    } else {
+        paths
+            .into_iter()
+            .map(|p| {
+                let src = std::fs::read_to_string(&p).expect("bundle should be readable");
+                (p, src)
+            })
+            .collect()
+    };
+
+    let mut rw = NativeRewriter::new();
+    for (name, src) in &inputs {
+        for prefilter in [false, true] {
+            let flags = Flags {
+                url: "https://example.com/bundle.js".into(),
+                prefilter,
+                ..Flags::default()
+            };
+            let (time, allocs, bytes, last) = measure(|| {
+                rw.rewrite_with(src.as_bytes(), flags.clone())
+                    .expect("rewrite should succeed")
+            });
+            println!(
+                "{name} ({} KiB), prefilter {}: {:.2?}/iter, {:.1} MiB/s, \
+                 {allocs} allocs/iter ({} KiB), {:?}",
+                src.len() >> 10,
+                if prefilter { "on" } else { "off" },
+                time,
+                src.len() as f64 / time.as_secs_f64() / (1 << 20) as f64,
+                bytes >> 10,
+                last.outcome,
+            );
+            println!(
+                "    last run: parse {:.2?}, visit {:.2?}, transform {:.2?}",
+                last.stats.parse, last.stats.visit, last.stats.transform,
+            );
+        }
+    }
+}
+
+/// Mean time, allocations and allocated bytes over enough runs to fill about
+/// a second, after one warm-up.
+fn measure<T>(mut f: impl FnMut() -> T) -> (Duration, u64, u64, T) {
+    let mut out = f();
+    let mut runs = 0u32;
+    let allocs = ALLOCS.load(Ordering::Relaxed);
+    let bytes = BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) || runs < 3 {
+        out = f();
+        runs += 1;
+    }
+    let time = start.elapsed() / runs;
+    let allocs = (ALLOCS.load(Ordering::Relaxed) - allocs) / runs as u64;
+    let bytes = (BYTES.load(Ordering::Relaxed) - bytes) / runs as u64;
+    (time, allocs, bytes, out)
+}
@@ -0,0 +1,83 @@
+use std::fmt::Write;
+
+use js::diagnostic::{Diagnostic, Label, LineIndex};
+
+/// Renders a diagnostic with the source lines its labels point at, in the
+/// style of rustc.
+pub fn render(diag: &Diagnostic, lines: &LineIndex) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{}[{}]: {}",
+        diag.severity.as_str(),
+        diag.code,
+        diag.message
+    );
+
+    let Some(span) = &diag.span else {
+        if !diag.url.is_empty() {
+            let _ = writeln!(out, "  --> {}", diag.url);
+        }
+        return out;
+    };
+
+    let name = if diag.url.is_empty() {
+        "<input>"
+    } else {
+        diag.url.as_str()
+    };
+    let _ = writeln!(
+        out,
+        "  --> {}:{}:{}",
+        name, span.start.line, span.start.column
+    );
+
+    let width = std::iter::once(span)
+        .chain(&diag.labels)
+        .map(|l| l.start.line.to_string().len())
+        .max()
+        .unwrap_or(1);
+    let _ = writeln!(out, "{:width$} |", "");
+    snippet(&mut out, lines, span, '^', width);
+    for label in &diag.labels {
+        snippet(&mut out, lines, label, '-', width);
+    }
+    if let Some(help) = &diag.help {
+        let _ = writeln!(out, "{:width$} = help: {help}", "");
+    }
+    out
+}
+
+fn snippet(out: &mut String, lines: &LineIndex, label: &Label, mark: char, width: usize) {
+    let text = lines.line(label.start.line);
+    let _ = writeln!(out, "{:>width$} | {text}", label.start.line);
+
+    let lead = char_prefix(text, label.start.column);
+    let len = if label.end.line == label.start.line {
+        char_prefix(text, label.end.column).saturating_sub(lead)
+    } else {
+        text.chars().count().saturating_sub(lead)
+    };
+    let _ = write!(
+        out,
+        "{:width$} | {}{}",
+        "",
+        " ".repeat(lead),
+        mark.to_string().repeat(len.max(1))
+    );
+    if let Some(message) = &label.message {
+        let _ = write!(out, " {message}");
+    }
+    out.push('\n');
+}
+
+/// Number of chars before a 1-based UTF-16 column.
+fn char_prefix(text: &str, column: u32) -> usize {
+    let mut units = 0;
+    text.chars()
+        .take_while(|c| {
+            units += c.len_utf16() as u32;
+            units < column
+        })
+        .count()
+}
@@ -1,2 +1,4 @@
+pub mod diagnostics;
 pub mod rewriter;
+pub mod site;
 pub mod test_runner;
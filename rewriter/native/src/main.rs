@@ -1,5 +1,13 @@
+use std::path::Path;
+
 use clap::{Parser, Subcommand};
-use native::{rewriter, test_runner};
+use js::{
+    budget::BudgetPolicy,
+    cfg::{Flags, InvalidJsPolicy},
+    diagnostic::LineIndex,
+    error::RewriteError,
+};
+use native::{diagnostics, rewriter, site::SiteInventory, test_runner};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
@@ -15,8 +23,76 @@ enum Command {
         input: String,
         #[arg(long, default_value = "about:blank")]
         base: String,
+        /// URL the script was loaded from, for patch rules and diagnostics.
+        /// Defaults to the input path.
+        #[arg(long)]
+        url: Option<String>,
+        /// JSON file with patch rules to apply.
+        #[arg(long)]
+        rules: Option<String>,
+        /// JSON file with init scripts to insert.
+        #[arg(long)]
+        init: Option<String>,
+        /// Detected from the source when not given.
+        #[arg(long)]
+        module: Option<bool>,
+        /// Charset label of the input. A BOM overrides it; UTF-8 otherwise.
+        #[arg(long)]
+        charset: Option<String>,
+        /// passthrough, best-effort or reject.
+        #[arg(long, default_value = "best-effort")]
+        invalid_js: InvalidJsPolicy,
+        /// Re-parse the output and report anything the rewrite broke.
+        #[arg(long, default_value_t = false)]
+        verify: bool,
+        /// Print rewrite counts, timings and sizes.
         #[arg(long, default_value_t = false)]
-        module: bool,
+        stats: bool,
+        /// Print every rewrite with its position and what produced it.
+        #[arg(long, default_value_t = false)]
+        provenance: bool,
+        /// Print the output formatted for reading.
+        #[arg(long, default_value_t = false)]
+        pretty: bool,
+        /// Where to write the source map from the formatted output to the
+        /// input.
+        #[arg(long, requires = "pretty")]
+        pretty_map: Option<String>,
+        /// Leave inputs longer than this many bytes alone.
+        #[arg(long)]
+        max_input_size: Option<usize>,
+        /// Leave scripts needing more rewrites than this alone.
+        #[arg(long)]
+        max_changes: Option<usize>,
+        /// Give up on scripts taking longer than this to rewrite.
+        #[arg(long)]
+        time_budget_ms: Option<u64>,
+        /// Give up on scripts taking more than this many steps to visit.
+        #[arg(long)]
+        max_steps: Option<u64>,
+        /// passthrough or reject, for scripts over a budget.
+        #[arg(long, default_value = "passthrough")]
+        over_budget: BudgetPolicy,
+    },
+    /// Report ways a script may get around the rewrite, without rewriting it.
+    Audit {
+        #[arg(long)]
+        input: String,
+        /// Detected from the source when not given.
+        #[arg(long)]
+        module: Option<bool>,
+        /// Charset label of the input. A BOM overrides it; UTF-8 otherwise.
+        #[arg(long)]
+        charset: Option<String>,
+    },
+    /// Count the browser APIs a site's scripts use and suggest `siteFlags`.
+    Inventory {
+        /// Directory holding the site's scripts.
+        #[arg(long)]
+        dir: String,
+        /// `siteFlags` pattern for the site.
+        #[arg(long, default_value = ".*")]
+        site: String,
     },
     Test {
         #[arg(long, default_value = "tests")]
@@ -30,13 +106,158 @@ fn main() -> anyhow::Result<()> {
         Command::Rewrite {
             input,
             base,
+            url,
+            rules,
+            init,
             module,
+            charset,
+            invalid_js,
+            verify,
+            stats,
+            provenance,
+            pretty,
+            pretty_map,
+            max_input_size,
+            max_changes,
+            time_budget_ms,
+            max_steps,
+            over_budget,
         } => {
-            let text = std::fs::read_to_string(&input)?;
+            let bytes = std::fs::read(&input)?;
+            let decoded = js::charset::decode(&bytes, charset.as_deref());
+            let lines = LineIndex::new(&decoded.text);
             let mut rw = rewriter::NativeRewriter::new();
-            let out = rw.rewrite(text.as_bytes(), base, input, module)?;
-            println!("{}", String::from_utf8_lossy(&out.js));
+            if let Some(rules) = rules {
+                rw.load_patch_rules(&std::fs::read_to_string(rules)?)?;
+            }
+            if let Some(init) = init {
+                rw.load_init_scripts(&std::fs::read_to_string(init)?)?;
+            }
+            let flags = Flags {
+                base,
+                url: url.unwrap_or(input),
+                is_module: module.into(),
+                invalid_js,
+                charset,
+                verify,
+                provenance,
+                pretty,
+                max_input_size,
+                max_changes,
+                time_budget: time_budget_ms.map(std::time::Duration::from_millis),
+                max_steps,
+                over_budget,
+                ..Flags::default()
+            };
+            let out = match rw.rewrite_with(&bytes, flags) {
+                Ok(out) => out,
+                Err(e) => {
+                    if let Some(RewriteError::InvalidJs { errors }) = e.downcast_ref() {
+                        for err in errors {
+                            eprint!("{}", diagnostics::render(err, &lines));
+                        }
+                    }
+                    return Err(e);
+                }
+            };
+            match &out.pretty {
+                Some(formatted) => {
+                    println!("{}", formatted.code);
+                    if let Some(path) = pretty_map {
+                        std::fs::write(path, &formatted.map)?;
+                    }
+                }
+                None => {
+                    if pretty {
+                        eprintln!("output does not parse, printing it unformatted");
+                    }
+                    println!("{}", String::from_utf8_lossy(&out.js));
+                }
+            }
+            eprintln!("kind: {:?}", out.kind);
+            eprintln!("encoding: {}", out.source_encoding);
+            eprintln!("outcome: {:?}", out.outcome);
+            for err in &out.errors {
+                eprint!("{}", diagnostics::render(err, &lines));
+            }
             eprintln!("errors: {}", out.errors.len());
+            for rule in &out.applied_rules {
+                eprintln!("applied rule: {rule}");
+            }
+            for script in &out.injected_scripts {
+                eprintln!("injected script: {script}");
+            }
+            if stats {
+                let s = &out.stats;
+                for (kind, count) in &s.counts {
+                    eprintln!("{kind}: {count}");
+                }
+                eprintln!(
+                    "prefilter: {:?}, parse: {:?}, visit: {:?}, transform: {:?}",
+                    s.prefilter, s.parse, s.visit, s.transform
+                );
+                eprintln!("size: {} -> {} bytes", s.input_size, s.output_size);
+            }
+            for p in &out.provenance {
+                let start = lines.position(p.span.start);
+                eprintln!(
+                    "{}:{}: {} from {}",
+                    start.line, start.column, p.kind, p.source
+                );
+            }
+            if verify {
+                let output = String::from_utf8_lossy(&out.js);
+                let output_lines = LineIndex::new(&output);
+                for regression in &out.regressions {
+                    eprint!("{}", diagnostics::render(regression, &output_lines));
+                }
+                eprintln!("regressions: {}", out.regressions.len());
+            }
+        }
+        Command::Audit {
+            input,
+            module,
+            charset,
+        } => {
+            let bytes = std::fs::read(&input)?;
+            let decoded = js::charset::decode(&bytes, charset.as_deref());
+            let lines = LineIndex::new(&decoded.text);
+            let flags = Flags {
+                url: input.clone(),
+                is_module: module.into(),
+                ..Flags::default()
+            };
+            let audit = rewriter::NativeRewriter::new().audit(&decoded.text, &flags);
+            for err in &audit.errors {
+                eprint!("{}", diagnostics::render(err, &lines));
+            }
+            for finding in &audit.findings {
+                let start = lines.position(finding.span.start);
+                println!(
+                    "{input}:{}:{}: {}: {}",
+                    start.line,
+                    start.column,
+                    finding.category.as_str(),
+                    finding.category.description()
+                );
+            }
+            eprintln!("findings: {}", audit.findings.len());
+        }
+        Command::Inventory { dir, site } => {
+            let inventory = SiteInventory::scan(Path::new(&dir))?;
+            eprintln!(
+                "scripts: {} ({} with syntax errors)",
+                inventory.scripts, inventory.invalid
+            );
+            for (capability, count) in &inventory.counts {
+                eprintln!(
+                    "{}: {count} in {} scripts",
+                    capability.as_str(),
+                    inventory.scripts_using[capability]
+                );
+            }
+            let flags = serde_json::json!({ site: inventory.recommended_flags() });
+            println!("{}", serde_json::to_string_pretty(&flags)?);
         }
         Command::Test { dir } => {
             test_runner::run(&dir)?;
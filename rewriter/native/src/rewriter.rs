@@ -2,7 +2,7 @@ use std::error::Error;
 
 use js::{
     Rewriter as JsRewriter,
-    cfg::{Config, Flags, StringBuilder, UrlRewriter},
+    cfg::{Config, Flags, StringBuilder, UrlDestination, UrlRewriter},
 };
 
 pub struct NativeUrlRewriter;
@@ -14,10 +14,12 @@ impl UrlRewriter for NativeUrlRewriter {
         _flags: &Flags,
         url: &str,
         builder: &mut StringBuilder,
-        _module: bool,
+        dest: UrlDestination,
+        module: bool,
     ) -> Result<(), Box<dyn Error + Sync + Send>> {
         builder.push_str(&cfg.prefix);
         builder.push_str(url);
+        dest.push_query(module, builder);
         Ok(())
     }
 }
@@ -34,23 +36,54 @@ impl Default for NativeRewriter {
 
 impl NativeRewriter {
     pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    pub fn with_config(cfg: Config) -> Self {
         Self {
-            inner: JsRewriter::new(Config::default(), NativeUrlRewriter),
+            inner: JsRewriter::new(cfg, NativeUrlRewriter),
         }
     }
 
+    /// Adds patch rules from a JSON array.
+    pub fn load_patch_rules(&mut self, json: &str) -> anyhow::Result<()> {
+        self.inner.load_patch_rules(json)
+    }
+
+    /// Adds init scripts from a JSON array.
+    pub fn load_init_scripts(&mut self, json: &str) -> anyhow::Result<()> {
+        self.inner.load_init_scripts(json)
+    }
+
     pub fn rewrite(
         &mut self,
         js: &[u8],
         base: String,
-        _url: String,
-        module: bool,
+        url: String,
+        module: Option<bool>,
     ) -> anyhow::Result<js::RewriteResult> {
         let flags = Flags {
             base,
-            is_module: module,
+            url,
+            is_module: module.into(),
             ..Flags::default()
         };
+        self.rewrite_with(js, flags)
+    }
+
+    pub fn rewrite_with(&mut self, js: &[u8], flags: Flags) -> anyhow::Result<js::RewriteResult> {
         self.inner.rewrite_bytes(js, flags)
     }
+
+    pub fn rewrite_javascript_url(&self, url: &str, flags: Flags) -> String {
+        self.inner.rewrite_javascript_url(url, flags)
+    }
+
+    pub fn audit(&self, js: &str, flags: &Flags) -> js::audit::Audit {
+        self.inner.audit(js, flags)
+    }
+
+    pub fn inventory(&self, js: &str, flags: &Flags) -> js::inventory::Inventory {
+        self.inner.inventory(js, flags)
+    }
 }
@@ -0,0 +1,78 @@
+use std::{collections::BTreeMap, path::Path};
+
+use js::{
+    cfg::{Flags, ScriptKind},
+    charset,
+    inventory::{Capability, Inventory},
+};
+use walkdir::WalkDir;
+
+use crate::rewriter::NativeRewriter;
+
+/// Capability use summed over the scripts of one site.
+#[derive(Debug, Default)]
+pub struct SiteInventory {
+    pub scripts: usize,
+    /// Scripts that had parse errors.
+    pub invalid: usize,
+    pub counts: BTreeMap<Capability, usize>,
+    /// How many scripts use each capability.
+    pub scripts_using: BTreeMap<Capability, usize>,
+}
+
+impl SiteInventory {
+    /// Inventories every `.js`, `.mjs` and `.cjs` file under `dir`.
+    pub fn scan(dir: &Path) -> anyhow::Result<Self> {
+        let rw = NativeRewriter::new();
+        let mut site = Self::default();
+        for entry in WalkDir::new(dir).sort_by_file_name() {
+            let entry = entry?;
+            let path = entry.path();
+            let is_script = path
+                .extension()
+                .is_some_and(|ext| ext == "js" || ext == "mjs" || ext == "cjs");
+            if !entry.file_type().is_file() || !is_script {
+                continue;
+            }
+            let bytes = std::fs::read(path)?;
+            let flags = Flags {
+                url: path.display().to_string(),
+                is_module: if path.extension().is_some_and(|ext| ext == "mjs") {
+                    ScriptKind::Module
+                } else {
+                    ScriptKind::Auto
+                },
+                ..Flags::default()
+            };
+            let text = charset::decode(&bytes, None).text;
+            site.add(&rw.inventory(&text, &flags));
+        }
+        Ok(site)
+    }
+
+    pub fn add(&mut self, inventory: &Inventory) {
+        self.scripts += 1;
+        if !inventory.errors.is_empty() {
+            self.invalid += 1;
+        }
+        for (capability, count) in inventory.counts() {
+            *self.counts.entry(capability).or_default() += count;
+            *self.scripts_using.entry(capability).or_default() += 1;
+        }
+    }
+
+    pub fn uses(&self, capability: Capability) -> bool {
+        self.counts.contains_key(&capability)
+    }
+
+    /// `siteFlags` entries for what the scripts need: service workers and
+    /// sync XHR only when used, and invalid JS allowed when any script has
+    /// syntax errors.
+    pub fn recommended_flags(&self) -> BTreeMap<&'static str, bool> {
+        BTreeMap::from([
+            ("serviceworkers", self.uses(Capability::ServiceWorker)),
+            ("syncxhr", self.uses(Capability::SyncXhr)),
+            ("allowInvalidJs", self.invalid > 0),
+        ])
+    }
+}
@@ -1,6 +1,7 @@
 use std::{fs, path::Path};
 
 use boa_engine::{Context, Source};
+use js::{cfg::Flags, javascript_url};
 use walkdir::WalkDir;
 
 use crate::rewriter::NativeRewriter;
@@ -59,6 +60,11 @@ const HARNESS: &str = r#"
     set(_) { }
   });
 
+  globalThis.sourcemaps = {};
+  globalThis.$webrascal$pushsourcemap = function(tag, map) {
+    sourcemaps[tag] = map;
+  };
+
   globalThis.check = function(val) {
     if (val === globalThis || val === globalThis.top || val === "location") {
       throw new Error("unsafe value leaked");
@@ -68,6 +74,14 @@ const HARNESS: &str = r#"
 })();
 "#;
 
+/// Run after each fixture: every rewritten script registers its map, even
+/// one that is only a hashbang, whose line would comment out the call.
+const REGISTERED: &str = r#"
+if (Object.keys(sourcemaps).length !== 1) {
+  throw new Error("sourcemap was not registered");
+}
+"#;
+
 pub fn run(dir: &str) -> anyhow::Result<()> {
     let mut runner = NativeRewriter::new();
     let root = Path::new(dir);
@@ -90,14 +104,20 @@ pub fn run(dir: &str) -> anyhow::Result<()> {
             &src,
             "https://example.com/".to_string(),
             path.display().to_string(),
-            false,
+            Some(false),
         )?;
 
-        let rewritten_text = String::from_utf8_lossy(&rewritten.js).to_string();
-        let combined = format!("{}\n{}", HARNESS, rewritten_text);
+        // Browsers drop the BOM while decoding, so the engine never sees it.
+        let rewritten_text = String::from_utf8_lossy(&rewritten.js);
+        let rewritten_text = rewritten_text.trim_start_matches('\u{feff}');
 
+        // Evaluate the fixture as its own script so that hashbangs and
+        // directive prologues are seen where they would be in a browser.
         let mut context = Context::default();
-        let result = context.eval(Source::from_bytes(combined.as_bytes()));
+        let result = context
+            .eval(Source::from_bytes(HARNESS))
+            .and_then(|_| context.eval(Source::from_bytes(rewritten_text.as_bytes())))
+            .and_then(|_| context.eval(Source::from_bytes(REGISTERED)));
         match result {
             Ok(_) => {
                 passed += 1;
@@ -117,3 +137,74 @@ pub fn run(dir: &str) -> anyhow::Result<()> {
     println!("all tests passed ({passed})");
     Ok(())
 }
+
+/// Runs the `javascript:` URL fixtures in `dir`: each `.url` file is
+/// rewritten, decoded again the way a browser would, and has to evaluate to
+/// `"ok"` without leaking.
+pub fn run_javascript_urls(dir: &str) -> anyhow::Result<()> {
+    let runner = NativeRewriter::new();
+    let root = Path::new(dir);
+    if !root.exists() {
+        anyhow::bail!("test dir not found: {}", root.display());
+    }
+
+    let mut passed = 0usize;
+    let mut failed = 0usize;
+
+    for entry in WalkDir::new(root)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| e.path().extension().and_then(|x| x.to_str()) == Some("url"))
+    {
+        let path = entry.path();
+        let url = fs::read_to_string(path)?;
+        let flags = Flags {
+            base: "https://example.com/".to_string(),
+            ..Flags::default()
+        };
+        let rewritten = runner.rewrite_javascript_url(&url, flags);
+        let Some(script) = javascript_url::script(&rewritten) else {
+            failed += 1;
+            eprintln!(
+                "FAIL {} => not a javascript: URL: {rewritten}",
+                path.display()
+            );
+            continue;
+        };
+
+        let mut context = Context::default();
+        let result = context
+            .eval(Source::from_bytes(HARNESS))
+            .and_then(|_| context.eval(Source::from_bytes(script.as_bytes())));
+        match result {
+            Ok(value)
+                if value
+                    .as_string()
+                    .is_some_and(|s| s.to_std_string_escaped() == "ok") =>
+            {
+                passed += 1;
+                println!("PASS {}", path.display());
+            }
+            Ok(value) => {
+                failed += 1;
+                eprintln!(
+                    "FAIL {} => evaluated to {}",
+                    path.display(),
+                    value.display()
+                );
+            }
+            Err(err) => {
+                failed += 1;
+                eprintln!("FAIL {} => {err}", path.display());
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} test files failed ({} passed)", failed, passed);
+    }
+
+    println!("all tests passed ({passed})");
+    Ok(())
+}
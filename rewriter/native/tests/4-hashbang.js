@@ -0,0 +1,3 @@
+#!/usr/bin/env node
+check(top);
+check(location);
@@ -0,0 +1,2 @@
+﻿check(top);
+check(location);
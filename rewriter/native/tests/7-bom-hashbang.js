@@ -0,0 +1,6 @@
+﻿#!/usr/bin/env node
+"use strict"
+if ((function () { return this; })() !== undefined) {
+  throw new Error("directive prologue was broken");
+}
+check(parent);
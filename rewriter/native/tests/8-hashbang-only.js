@@ -0,0 +1 @@
+#!/usr/bin/env node
\ No newline at end of file
@@ -0,0 +1,10 @@
+"use strict";
+
+// The map has to be registered before any of the script's own code runs.
+if (Object.keys(sourcemaps).length !== 1) {
+  throw new Error("sourcemap was not registered");
+}
+if ((function () { return this; })() !== undefined) {
+  throw new Error("directive prologue was broken");
+}
+check(location);
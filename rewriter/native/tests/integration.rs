@@ -1,4 +1,1009 @@
+use std::{error::Error, time::Duration};
+
+use js::{
+    RewriteOutcome,
+    audit::EscapeCategory,
+    budget::{Budget, BudgetPolicy},
+    cfg::{Config, Flags, InvalidJsPolicy, ScriptKind, UrlDestination, UrlRewriter},
+    diagnostic::Diagnostic,
+    error::RewriteError,
+    inventory::Capability,
+    pass::{PassContext, RewritePass},
+    rewrite::{Rewrite, RewriteType},
+};
+use native::rewriter::{NativeRewriter, NativeUrlRewriter};
+use oxc::ast::{AstKind, ast::Expression};
+use transform::{OriginalPositions, stack::StackMaps};
+
+fn rewrite(src: &str, url: &str) -> String {
+    let out = NativeRewriter::new()
+        .rewrite(
+            src.as_bytes(),
+            "https://example.com/".to_string(),
+            url.to_string(),
+            Some(false),
+        )
+        .expect("rewrite should succeed");
+    String::from_utf8(out.js).expect("output should be utf-8")
+}
+
 #[test]
 fn rewrites_js_test_fixtures() {
     native::test_runner::run("tests").expect("native fixture tests should pass");
 }
+
+#[test]
+fn rewrites_javascript_url_fixtures() {
+    native::test_runner::run_javascript_urls("tests/javascript-urls")
+        .expect("javascript: URL fixtures should pass");
+}
+
+#[test]
+fn rewrites_only_javascript_urls() {
+    let rw = NativeRewriter::new();
+    for url in [
+        "https://example.com/",
+        "javascripts:top",
+        "java%73cript:top",
+    ] {
+        assert_eq!(rw.rewrite_javascript_url(url, Flags::default()), url);
+    }
+    let out = rw.rewrite_javascript_url("javascript:top", Flags::default());
+    assert!(out.starts_with("javascript:/*rascaltag%20"), "{out}");
+    assert!(out.ends_with("*/$webrascal$wrap(top)"), "{out}");
+}
+
+#[test]
+fn appends_source_url() {
+    let out = rewrite("check(top);\n// trailing", "https://example.com/a b.js");
+    assert!(out.ends_with("// trailing\n//# sourceURL=https://example.com/a%20b.js"));
+}
+
+#[test]
+fn replaces_existing_source_url() {
+    let out = rewrite(
+        "check(top);\n//# sourceURL=webpack://app/main.js\n",
+        "https://example.com/main.js",
+    );
+    assert!(out.contains("//# sourceURL=https://example.com/main.js\n"));
+    assert!(!out.contains("webpack://"));
+    assert_eq!(out.matches("sourceURL").count(), 1);
+}
+
+#[test]
+fn registers_sourcemap_after_hashbang_line() {
+    let src = std::fs::read_to_string("tests/8-hashbang-only.js").unwrap();
+    let out = rewrite(&src, "https://example.com/a.js");
+    let (hashbang, rest) = out.split_once('\n').expect("hashbang should end its line");
+    assert_eq!(hashbang, "#!/usr/bin/env node");
+    assert!(rest.starts_with("$webrascal$pushsourcemap("), "{out}");
+}
+
+#[test]
+fn source_tags_follow_input() {
+    let a = rewrite("check(top);", "https://example.com/a.js");
+    let b = rewrite("check(top);", "https://example.com/a.js");
+    let c = rewrite("check(parent);", "https://example.com/a.js");
+    assert_eq!(a, b);
+    assert_ne!(a.lines().next(), c.lines().next());
+
+    // Rules, init scripts and passes change the output as much as the input.
+    let tag = |rw: &js::Rewriter<NativeUrlRewriter>| {
+        rw.rewrite("check(top);", Flags::default())
+            .expect("rewrite should succeed")
+            .flags
+            .sourcetag
+    };
+    let new = || js::Rewriter::new(Config::default(), NativeUrlRewriter);
+    let plain = tag(&new());
+    let mut rules = new();
+    rules
+        .load_patch_rules(r#"[{"name": "x", "snippet": "top", "action": "delete"}]"#)
+        .expect("rules should parse");
+    let mut scripts = new();
+    scripts
+        .load_init_scripts(r#"[{"name": "x", "code": "1;"}]"#)
+        .expect("scripts should parse");
+    let mut passes = new();
+    passes.add_pass(NeutraliseFingerprint);
+    let tags = [plain, tag(&rules), tag(&scripts), tag(&passes)];
+    for (i, a) in tags.iter().enumerate() {
+        assert!(!tags[i + 1..].contains(a), "{tags:?}");
+    }
+}
+
+#[test]
+fn detects_script_kind() {
+    let kind = |src: &str| {
+        NativeRewriter::new()
+            .rewrite(
+                src.as_bytes(),
+                "https://example.com/".into(),
+                String::new(),
+                None,
+            )
+            .expect("rewrite should succeed")
+            .kind
+    };
+    assert_eq!(kind("check(top);"), ScriptKind::Script);
+    assert_eq!(kind("var await = 1; check(await);"), ScriptKind::Script);
+    assert_eq!(
+        kind("import x from './x.js'; check(x);"),
+        ScriptKind::Module
+    );
+    assert_eq!(kind("export const a = location;"), ScriptKind::Module);
+    assert_eq!(kind("check(import.meta.url);"), ScriptKind::Module);
+    assert_eq!(kind("await fetch('/x');"), ScriptKind::Module);
+}
+
+#[test]
+fn reports_diagnostic_positions() {
+    // Diagnostics need a parse, which the prefilter would skip here.
+    let out = NativeRewriter::new()
+        .rewrite_with(
+            "let a = 1;\n\u{feff}let é = foo(a b);".as_bytes(),
+            Flags {
+                base: "https://example.com/".into(),
+                url: "https://example.com/bad.js".into(),
+                prefilter: false,
+                ..Flags::default()
+            },
+        )
+        .expect("rewrite should succeed");
+    let err = &out.errors[0];
+    let span = err.span.as_ref().expect("parse errors have a span");
+    assert_eq!(err.url, "https://example.com/bad.js");
+    assert_eq!((span.start.line, span.start.column), (2, 16));
+    assert_eq!(span.start.offset, 29);
+    assert!(!err.labels.is_empty());
+}
+
+#[test]
+fn applies_invalid_js_policy() {
+    let src = "check(top);\nfoo(a b);";
+    let run = |invalid_js| {
+        NativeRewriter::new().rewrite_with(
+            src.as_bytes(),
+            Flags {
+                invalid_js,
+                ..Flags::default()
+            },
+        )
+    };
+
+    let out = run(InvalidJsPolicy::Passthrough).expect("passthrough should succeed");
+    assert_eq!(out.outcome, RewriteOutcome::Passthrough);
+    assert_eq!(out.js, src.as_bytes());
+    assert!(out.sourcemap.is_empty());
+
+    let out = run(InvalidJsPolicy::BestEffort).expect("best effort should succeed");
+    assert_eq!(out.outcome, RewriteOutcome::BestEffort);
+    assert!(String::from_utf8_lossy(&out.js).contains("$webrascal$wrap(top)"));
+
+    let err = run(InvalidJsPolicy::Reject).expect_err("reject should fail");
+    assert!(matches!(
+        err.downcast_ref(),
+        Some(RewriteError::InvalidJs { errors }) if errors.len() == 1
+    ));
+
+    let out = NativeRewriter::new()
+        .rewrite_with(b"check(top);", Flags::default())
+        .expect("valid js should rewrite");
+    assert_eq!(out.outcome, RewriteOutcome::Rewritten);
+}
+
+#[test]
+fn verify_reports_regressions() {
+    let cfg = js::cfg::Config::default();
+    let flags = Flags::default();
+    let unchanged = OriginalPositions::new(&[]);
+    let codes = |output: &str, input_errors: &[Diagnostic]| {
+        js::verify::verify(output, input_errors, &unchanged, &cfg, &flags)
+            .into_iter()
+            .map(|d| (d.code, d.span.map(|s| s.start.column)))
+            .collect::<Vec<_>>()
+    };
+
+    assert!(
+        codes(
+            "$webrascal$wrap(top).x; a.$webrascal__location; function f(top) { top; }",
+            &[]
+        )
+        .is_empty()
+    );
+    assert_eq!(
+        codes("$webrascal$wrap(top); top; x.location;", &[]),
+        [
+            ("webrascal(verify-leak)".to_string(), Some(23)),
+            ("webrascal(verify-leak)".to_string(), Some(30)),
+        ]
+    );
+    assert_eq!(codes("foo(", &[])[0].0, "webrascal(verify-syntax)");
+
+    // Errors the input had are matched by message and mapped position, not
+    // by count: breaking a script that already has one error is reported.
+    let regressions = |rules: &str| {
+        let mut rw = NativeRewriter::new();
+        rw.load_patch_rules(rules).expect("rules should parse");
+        let out = rw
+            .rewrite_with(
+                b"top.x = 1;\nfoo(",
+                Flags {
+                    verify: true,
+                    ..Flags::default()
+                },
+            )
+            .expect("best effort should rewrite");
+        assert_eq!(out.errors.len(), 1);
+        out.regressions
+    };
+    assert!(regressions("[]").is_empty());
+    let broken = regressions(
+        r#"[{"name": "break", "snippet": "= 1;", "action": "insert-after", "text": ")"}]"#,
+    );
+    assert_eq!(broken[0].code, "webrascal(verify-syntax)");
+}
+
+#[test]
+fn honours_configured_unsafe_globals() {
+    let mut cfg = js::cfg::Config::default();
+    cfg.unsafe_globals.push("opener".into());
+    cfg.unsafe_properties.push("frames".into());
+    let out = NativeRewriter::with_config(cfg)
+        .rewrite_with(b"opener.focus(); a.frames; frames;", Flags::default())
+        .expect("rewrite should succeed");
+    let out = String::from_utf8(out.js).unwrap();
+    assert!(out.contains("$webrascal$wrap(opener).focus()"));
+    assert!(out.contains("a.$webrascal__frames;"));
+    assert!(out.contains(" frames;"));
+}
+
+struct NeutraliseFingerprint;
+
+impl RewritePass for NeutraliseFingerprint {
+    fn name(&self) -> &str {
+        "neutralise-fingerprint"
+    }
+
+    fn run(&self, cx: &PassContext<'_>) -> Vec<Rewrite> {
+        cx.semantic
+            .nodes()
+            .iter()
+            .filter_map(|node| match node.kind() {
+                AstKind::CallExpression(call)
+                    if matches!(&call.callee, Expression::Identifier(id) if id.name == "fingerprint") =>
+                {
+                    Some(Rewrite {
+                        span: cx.source_span(call.span),
+                        ty: RewriteType::Replace { text: "0".into() },
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn runs_custom_passes() {
+    let mut rw = js::Rewriter::new(js::cfg::Config::default(), NativeUrlRewriter);
+    rw.add_pass(NeutraliseFingerprint);
+    let out = rw
+        .rewrite(
+            "\u{feff}let id = fingerprint(top);\ncheck(top);",
+            Flags::default(),
+        )
+        .expect("rewrite should succeed");
+    let out = String::from_utf8(out.js).unwrap();
+    assert!(out.contains("let id = 0;\ncheck($webrascal$wrap(top));"));
+}
+
+#[test]
+fn applies_patch_rules() {
+    let mut rw = NativeRewriter::new();
+    rw.load_patch_rules(
+        r#"[
+            {"name": "battery", "host": "*.example.com", "call": "navigator.getBattery",
+             "action": "replace", "text": "Promise.reject()"},
+            {"name": "ads", "url": "https://ads.test/*", "snippet": "loadAds();", "action": "delete"},
+            {"name": "debug", "identifier": "debugMode", "action": "insert-before", "text": "!"}
+        ]"#,
+    )
+    .expect("rules should parse");
+    let src = b"navigator.getBattery().then(f); loadAds(); if (debugMode) {}";
+
+    let out = rw
+        .rewrite(
+            src,
+            String::new(),
+            "https://www.example.com/app.js".into(),
+            Some(false),
+        )
+        .expect("rewrite should succeed");
+    let js = String::from_utf8(out.js).unwrap();
+    assert!(js.contains("*/Promise.reject().then(f); loadAds(); if (!debugMode) {}"));
+    assert_eq!(out.applied_rules, ["battery", "debug"]);
+
+    let out = rw
+        .rewrite(
+            src,
+            String::new(),
+            "https://ads.test/x.js".into(),
+            Some(false),
+        )
+        .expect("rewrite should succeed");
+    let js = String::from_utf8(out.js).unwrap();
+    assert!(js.contains("*/navigator.getBattery().then(f);  if (!debugMode) {}"));
+    assert_eq!(out.applied_rules, ["ads", "debug"]);
+
+    assert!(
+        rw.load_patch_rules(r#"[{"name": "x", "action": "delete"}]"#)
+            .is_err()
+    );
+}
+
+#[test]
+fn patch_rules_replace_overlapping_rewrites_whole() {
+    let mut rw = js::Rewriter::new(js::cfg::Config::default(), NativeUrlRewriter);
+    rw.load_patch_rules(
+        r#"[
+            {"name": "snip", "snippet": "(top", "action": "replace", "text": "(42"},
+            {"name": "late", "snippet": "top)", "action": "replace", "text": "0)"}
+        ]"#,
+    )
+    .expect("rules should parse");
+    let flags = Flags {
+        verify: true,
+        ..Flags::default()
+    };
+    let out = rw
+        .rewrite("location.href = \"/x\";\nfoo(top);", flags)
+        .expect("rewrite should succeed");
+    let js = String::from_utf8(out.js).unwrap();
+    assert!(js.contains("\nfoo(42);"), "{js}");
+    assert!(!js.contains("$webrascal$wrap(42"), "{js}");
+    assert_eq!(out.applied_rules, ["snip"]);
+    assert!(out.regressions.is_empty(), "{:?}", out.regressions);
+}
+
+#[test]
+fn injects_init_scripts() {
+    let mut rw = NativeRewriter::new();
+    rw.load_init_scripts(
+        r#"[
+            {"name": "polyfill", "host": "example.com", "code": "const x = 1; globalThis.ready = x;"},
+            {"name": "module-only", "kind": "module", "code": "const y = 2;"}
+        ]"#,
+    )
+    .expect("init scripts should parse");
+    let flags = |url: &str, kind: ScriptKind| Flags {
+        url: url.into(),
+        is_module: kind,
+        do_sourcemaps: false,
+        verify: true,
+        ..Flags::default()
+    };
+
+    let out = rw
+        .rewrite_with(
+            b"#!/usr/bin/env node\n'use strict'\nconst x = 0;",
+            flags("https://example.com/a.js", ScriptKind::Script),
+        )
+        .expect("rewrite should succeed");
+    assert_eq!(
+        String::from_utf8(out.js).unwrap(),
+        "#!/usr/bin/env node\n'use strict';{const x = 1; globalThis.ready = x;\n}\nconst x = 0;\n//# sourceURL=https://example.com/a.js"
+    );
+    assert_eq!(out.injected_scripts, ["polyfill"]);
+    assert!(out.regressions.is_empty());
+
+    let out = rw
+        .rewrite_with(
+            b"import a from 'a'; const y = a;",
+            flags("https://example.com/m.js", ScriptKind::Module),
+        )
+        .expect("rewrite should succeed");
+    assert_eq!(out.injected_scripts, ["polyfill", "module-only"]);
+    assert!(out.regressions.is_empty(), "{:?}", out.regressions);
+
+    let out = rw
+        .rewrite_with(b"#!x", flags("https://other.test/", ScriptKind::Script))
+        .expect("rewrite should succeed");
+    assert!(out.injected_scripts.is_empty());
+}
+
+#[test]
+fn maps_init_scripts_as_injected() {
+    let mut rw = NativeRewriter::new();
+    rw.load_init_scripts(r#"[{"name": "probe", "code": "probe();"}]"#)
+        .expect("init scripts should parse");
+    let src = "main();\n";
+    let out = rw
+        .rewrite_with(
+            src.as_bytes(),
+            Flags {
+                url: "https://example.com/a.js".into(),
+                ..Flags::default()
+            },
+        )
+        .expect("rewrite should succeed");
+    let js = String::from_utf8(out.js).unwrap();
+    assert_eq!(
+        transform::original_text(&out.sourcemap, 0, &js).unwrap(),
+        src
+    );
+
+    let mut maps = StackMaps::default();
+    assert!(maps.register(
+        &out.flags.sourcetag,
+        "https://example.com/a.js",
+        &js,
+        &out.sourcemap
+    ));
+    let frame = |needle: &str| {
+        let at = js.find(needle).unwrap();
+        let line = js[..at].matches('\n').count() + 1;
+        let column = at - js[..at].rfind('\n').map_or(0, |i| i + 1) + 1;
+        format!("    at https://example.com/a.js:{line}:{column}\n")
+    };
+    let stack = format!("Error\n{}{}", frame("probe"), frame("main"));
+    assert_eq!(
+        maps.rewrite(&stack),
+        "Error\n    at https://example.com/a.js:1:1\n"
+    );
+}
+
+#[test]
+fn reports_stats_and_provenance() {
+    let mut rw = js::Rewriter::new(js::cfg::Config::default(), NativeUrlRewriter);
+    rw.add_pass(NeutraliseFingerprint);
+    rw.load_patch_rules(r#"[{"name": "quiet", "call": "console.log", "action": "delete"}]"#)
+        .unwrap();
+    let src = "fingerprint(top); console.log(parent); top.x;";
+    let flags = Flags {
+        url: "https://example.com/a.js".into(),
+        provenance: true,
+        ..Flags::default()
+    };
+    let out = rw.rewrite(src, flags).expect("rewrite should succeed");
+
+    assert_eq!(out.stats.input_size, src.len());
+    assert_eq!(out.stats.output_size, out.js.len());
+    assert_eq!(out.stats.counts.get("SourceTag"), Some(&1));
+    assert_eq!(out.stats.counts.get("WrapFn"), Some(&1));
+    assert_eq!(out.stats.counts.get("Replace"), Some(&1));
+    assert_eq!(out.stats.counts.get("Delete"), Some(&1));
+
+    let sources = out
+        .provenance
+        .iter()
+        .map(|p| {
+            (
+                p.kind,
+                &src[p.span.start as usize..p.span.end as usize],
+                p.source.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert!(sources.contains(&("WrapFn", "top", "visit_identifier_reference")));
+    assert!(sources.contains(&("Replace", "fingerprint(top)", "neutralise-fingerprint")));
+    assert!(sources.contains(&("Delete", "console.log(parent)", "patch:quiet")));
+    assert_eq!(
+        out.provenance.len(),
+        out.stats.counts.values().sum::<u32>() as usize
+    );
+
+    let out = rw.rewrite(src, Flags::default()).unwrap();
+    assert!(out.provenance.is_empty());
+}
+
+#[test]
+fn prefilter_matches_full_rewrite() {
+    let run = |src: &str, is_module: ScriptKind, prefilter: bool| {
+        NativeRewriter::new()
+            .rewrite_with(
+                src.as_bytes(),
+                Flags {
+                    url: "https://example.com/lib.js".into(),
+                    is_module,
+                    prefilter,
+                    ..Flags::default()
+                },
+            )
+            .expect("rewrite should succeed")
+    };
+
+    for (src, kind) in [
+        ("let a = 1;", ScriptKind::Script),
+        ("function stop() { return desktop + 1; }", ScriptKind::Auto),
+        ("#!/usr/bin/env node\nfoo();", ScriptKind::Script),
+        ("#!/usr/bin/env node", ScriptKind::Script),
+        ("\u{feff}/* header */ foo();", ScriptKind::Module),
+        ("x = [1, 2];", ScriptKind::Script),
+    ] {
+        let fast = run(src, kind, true);
+        let full = run(src, kind, false);
+        assert_eq!(fast.outcome, RewriteOutcome::Prefiltered, "{src}");
+        assert_eq!(fast.js, full.js, "{src}");
+        assert_eq!(fast.sourcemap, full.sourcemap, "{src}");
+        assert_eq!(fast.kind, full.kind, "{src}");
+    }
+
+    for src in [
+        "'use strict'; foo();",
+        "a[i];",
+        "top.x;",
+        "x.postMessage(1);",
+        "import('a');",
+        "//# sourceURL=a.js",
+        "importScripts('a.js');",
+    ] {
+        assert_ne!(
+            run(src, ScriptKind::Script, true).outcome,
+            RewriteOutcome::Prefiltered,
+            "{src}"
+        );
+    }
+    let out = run("export const a = 1;", ScriptKind::Auto, true);
+    assert_eq!(out.kind, ScriptKind::Module);
+}
+
+#[test]
+fn applies_budgets() {
+    let src = "check(top);\ncheck(parent);";
+    let run = |flags: Flags| NativeRewriter::new().rewrite_with(src.as_bytes(), flags);
+
+    let out = run(Flags {
+        max_input_size: Some(8),
+        ..Flags::default()
+    })
+    .expect("over budget should pass through");
+    assert_eq!(out.outcome, RewriteOutcome::OverBudget(Budget::InputSize));
+    assert_eq!(out.js, src.as_bytes());
+    assert_eq!(out.errors[0].code, "webrascal(budget)");
+
+    let out = run(Flags {
+        max_changes: Some(1),
+        ..Flags::default()
+    })
+    .expect("over budget should pass through");
+    assert_eq!(out.outcome, RewriteOutcome::OverBudget(Budget::Changes));
+    assert_eq!(out.js, src.as_bytes());
+
+    let out = run(Flags {
+        time_budget: Some(Duration::ZERO),
+        prefilter: false,
+        ..Flags::default()
+    })
+    .expect("over budget should pass through");
+    assert_eq!(out.outcome, RewriteOutcome::OverBudget(Budget::Time));
+
+    let out = run(Flags {
+        max_steps: Some(4),
+        prefilter: false,
+        ..Flags::default()
+    })
+    .expect("over budget should pass through");
+    assert_eq!(out.outcome, RewriteOutcome::OverBudget(Budget::Steps));
+    assert_eq!(out.js, src.as_bytes());
+
+    let err = run(Flags {
+        max_changes: Some(1),
+        over_budget: BudgetPolicy::Reject,
+        ..Flags::default()
+    })
+    .expect_err("reject should fail");
+    assert!(matches!(
+        err.downcast_ref(),
+        Some(RewriteError::OverBudget {
+            budget: Budget::Changes
+        })
+    ));
+
+    let out = run(Flags {
+        max_input_size: Some(src.len()),
+        max_changes: Some(100),
+        time_budget: Some(Duration::from_secs(60)),
+        max_steps: Some(10_000),
+        ..Flags::default()
+    })
+    .expect("within budget should rewrite");
+    assert_eq!(out.outcome, RewriteOutcome::Rewritten);
+}
+
+#[test]
+fn decodes_input_charsets() {
+    let run = |bytes: &[u8], charset: Option<&str>| {
+        NativeRewriter::new()
+            .rewrite_with(
+                bytes,
+                Flags {
+                    charset: charset.map(str::to_string),
+                    ..Flags::default()
+                },
+            )
+            .expect("rewrite should succeed")
+    };
+
+    let out = run(b"check(top); // caf\xe9", Some("latin1"));
+    assert_eq!(out.source_encoding, "windows-1252");
+    let js = String::from_utf8(out.js).expect("output should be utf-8");
+    assert!(js.contains("$webrascal$wrap(top)"));
+    assert!(js.contains("caf\u{e9}"));
+
+    let out = run(b"var s = '\x82\xa0'; check(top);", Some("Shift_JIS"));
+    assert_eq!(out.source_encoding, "Shift_JIS");
+    assert!(String::from_utf8_lossy(&out.js).contains("'\u{3042}'"));
+
+    // The BOM wins over the label.
+    let mut utf16 = vec![0xff, 0xfe];
+    utf16.extend("check(top);".encode_utf16().flat_map(u16::to_le_bytes));
+    let out = run(&utf16, Some("windows-1252"));
+    assert_eq!(out.source_encoding, "UTF-16LE");
+    assert!(String::from_utf8_lossy(&out.js).contains("$webrascal$wrap(top)"));
+
+    let out = run(b"check(top); // \xff", Some("not-a-charset"));
+    assert_eq!(out.source_encoding, "UTF-8");
+    assert!(String::from_utf8_lossy(&out.js).contains('\u{fffd}'));
+    assert_eq!(out.errors[0].code, "webrascal(charset)");
+}
+
+#[test]
+fn audits_escape_vectors() {
+    let src = "window[k];\nwindow['top'];\nwith (o) {}\nnew Function('return ' + s);\n\
+        Function('return 1');\ndocument.domain = 'a.com';\narguments.callee.caller;\n\
+        a.__proto__.__proto__;\na.__proto__;\n$webrascal$wrap(top);";
+    let audit = NativeRewriter::new().audit(src, &Flags::default());
+    assert!(audit.errors.is_empty());
+    let found = audit
+        .findings
+        .iter()
+        .map(|f| (f.category, &src[f.span.start as usize..f.span.end as usize]))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        [
+            (EscapeCategory::DynamicGlobalProperty, "window[k]"),
+            (EscapeCategory::With, "with (o) {}"),
+            (
+                EscapeCategory::DynamicFunction,
+                "new Function('return ' + s)"
+            ),
+            (EscapeCategory::DocumentDomain, "document.domain = 'a.com'"),
+            (EscapeCategory::CalleeCaller, "arguments.callee.caller"),
+            (EscapeCategory::ProtoWalk, "a.__proto__.__proto__"),
+            (EscapeCategory::RuntimeName, "$webrascal$wrap"),
+        ]
+    );
+}
+
+#[test]
+fn inventories_capabilities() {
+    let src = "new WebSocket(u);\nnavigator.serviceWorker.register('/sw.js');\n\
+        xhr.open('GET', u, false);\nxhr.open('GET', u);\ndocument.write(s);\n\
+        import(u);\nnew SharedArrayBuffer(8);\neval(s);\neval(s);\n\
+        function f(eval) { eval(s); }";
+    let inventory = NativeRewriter::new().inventory(src, &Flags::default());
+    let counts = inventory.counts();
+    assert_eq!(counts[&Capability::WebSocket], 1);
+    assert_eq!(counts[&Capability::ServiceWorker], 1);
+    assert_eq!(counts[&Capability::SyncXhr], 1);
+    assert_eq!(counts[&Capability::DocumentWrite], 1);
+    assert_eq!(counts[&Capability::DynamicImport], 1);
+    assert_eq!(counts[&Capability::SharedArrayBuffer], 1);
+    assert_eq!(counts[&Capability::Eval], 2);
+    let first = &inventory.uses[0];
+    assert_eq!(
+        &src[first.span.start as usize..first.span.end as usize],
+        "new WebSocket(u)"
+    );
+
+    let mut site = native::site::SiteInventory::default();
+    site.add(&inventory);
+    let flags = site.recommended_flags();
+    assert!(flags["serviceworkers"] && flags["syncxhr"]);
+    assert!(!flags["allowInvalidJs"]);
+}
+
+#[test]
+fn pretty_output_maps_to_input() {
+    let src = "var a=1;if(a){check(top)}else{x[y]=parent.location}";
+    let out = NativeRewriter::new()
+        .rewrite_with(
+            src.as_bytes(),
+            Flags {
+                pretty: true,
+                ..Flags::default()
+            },
+        )
+        .expect("rewrite should succeed");
+    let pretty = out.pretty.expect("output should format");
+    assert!(pretty.code.contains("\n  check($webrascal$wrap(top));\n"));
+
+    let map = oxc_sourcemap::SourceMap::from_json_string(&pretty.map).expect("map should parse");
+    let lines = pretty.code.lines().collect::<Vec<_>>();
+    let source = |needle: &str| {
+        let line = lines.iter().position(|l| l.contains(needle)).unwrap();
+        let col = lines[line].find(needle).unwrap() as u32;
+        let token = map
+            .get_tokens()
+            .find(|t| t.get_dst_line() == line as u32 && t.get_dst_col() == col)
+            .expect("token should be mapped");
+        assert_eq!(token.get_src_line(), 0);
+        token.get_src_col() as usize
+    };
+    assert_eq!(source("top)"), src.find("top").unwrap());
+    assert_eq!(source("parent)"), src.find("parent").unwrap());
+    assert_eq!(
+        source("$webrascal__location"),
+        src.find("location").unwrap()
+    );
+}
+
+/// Writes down what it was asked so tests can see the destination, module
+/// kind and base of each static URL rewrite.
+struct TaggingUrlRewriter;
+
+impl UrlRewriter for TaggingUrlRewriter {
+    fn rewrite(
+        &self,
+        _cfg: &Config,
+        flags: &Flags,
+        url: &str,
+        builder: &mut String,
+        dest: UrlDestination,
+        module: bool,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        if url == "bad" {
+            return Err("unrewritable".into());
+        }
+        let kind = if module { "module" } else { "classic" };
+        builder.push_str(&format!("{}|{kind}|{}|{url}", dest.as_str(), flags.base));
+        Ok(())
+    }
+}
+
+#[test]
+fn rewrites_worker_urls_statically() {
+    let rw = js::Rewriter::new(Config::default(), TaggingUrlRewriter);
+    let run = |src: &str, is_module: ScriptKind| {
+        let out = rw
+            .rewrite(
+                src,
+                Flags {
+                    base: "https://example.com/".into(),
+                    url: "https://example.com/app/main.js".into(),
+                    is_module,
+                    ..Flags::default()
+                },
+            )
+            .expect("rewrite should succeed");
+        String::from_utf8(out.js).unwrap()
+    };
+
+    let out = run(
+        "new Worker(new URL('./w.js', import.meta.url), { type: 'module' });",
+        ScriptKind::Module,
+    );
+    assert!(
+        out.contains(r#"new Worker("worker|module|https://example.com/app/main.js|./w.js", { type: 'module' });"#),
+        "{out}"
+    );
+
+    let out = run(
+        "navigator.serviceWorker.register('/sw.js');\n\
+         new SharedWorker(new URL('s.js', 'https://cdn.example/x/'));\n\
+         CSS.paintWorklet.addModule(`paint.js`);\n\
+         importScripts('a.js', \"b.js\");",
+        ScriptKind::Script,
+    );
+    for expected in [
+        r#"register("serviceworker|classic|https://example.com/|/sw.js")"#,
+        r#"new SharedWorker("sharedworker|classic|https://cdn.example/x/|s.js")"#,
+        r#"addModule("paintworklet|module|https://example.com/|paint.js")"#,
+        r#"importScripts("script|classic|https://example.com/|a.js", "script|classic|https://example.com/|b.js")"#,
+    ] {
+        assert!(out.contains(expected), "{expected} in {out}");
+    }
+
+    for src in [
+        "new Worker(url);",
+        "new Worker('w.js', options);",
+        "new Worker(`${dir}/w.js`);",
+        "new Worker(new URL('w.js', location.href));",
+        "importScripts('bad');",
+    ] {
+        let out = run(src, ScriptKind::Script);
+        assert!(!out.contains('|'), "{src} gave {out}");
+    }
+}
+
+#[test]
+fn worker_urls_carry_destination() {
+    let out = rewrite(
+        "new Worker(\"x.js\");\nnew Worker(\"m.js\", { type: \"module\" });\nimportScripts(\"s.js\");",
+        "https://example.com/main.js",
+    );
+    assert!(
+        out.contains(r#"new Worker("/webrascal/x.js?dest=worker");"#),
+        "{out}"
+    );
+    assert!(
+        out.contains(
+            r#"new Worker("/webrascal/m.js?dest=worker&type=module", { type: "module" });"#
+        ),
+        "{out}"
+    );
+    assert!(
+        out.contains(r#"importScripts("/webrascal/s.js");"#),
+        "{out}"
+    );
+
+    // Only the worker global's importScripts loads scripts.
+    let out = rewrite(
+        "self.importScripts(\"a.js\"); loader.importScripts(\"b.js\");",
+        "https://example.com/main.js",
+    );
+    assert!(
+        out.contains(r#"self.importScripts("/webrascal/a.js");"#),
+        "{out}"
+    );
+    assert!(out.contains(r#"loader.importScripts("b.js");"#), "{out}");
+    let out = rewrite(
+        "function importScripts(url) {}\nimportScripts(\"c.js\");",
+        "https://example.com/main.js",
+    );
+    assert!(out.contains(r#"importScripts("c.js");"#), "{out}");
+
+    // A codec that leaves the query as it is gets the parameters added to it.
+    let out = rewrite(
+        "new Worker(\"w.js?v=2\");\nnew Worker(\"m.js#x\", { type: \"module\" });",
+        "https://example.com/main.js",
+    );
+    assert!(
+        out.contains(r#"new Worker("/webrascal/w.js?v=2&dest=worker");"#),
+        "{out}"
+    );
+    assert!(
+        out.contains(
+            r#"new Worker("/webrascal/m.js?dest=worker&type=module#x", { type: "module" });"#
+        ),
+        "{out}"
+    );
+}
+
+#[test]
+fn rewrites_handler_bodies() {
+    let rw = js::Rewriter::new(Config::default(), NativeUrlRewriter);
+    let flags = || Flags {
+        url: "https://example.com/".into(),
+        ..Flags::default()
+    };
+
+    let out = rw
+        .rewrite_handler("if (top !== self) return false; // bail", flags())
+        .expect("rewrite should succeed");
+    assert_eq!(out.outcome, RewriteOutcome::Rewritten);
+    assert!(out.errors.is_empty());
+    let js = String::from_utf8(out.js).unwrap();
+    assert_eq!(
+        js,
+        "if ($webrascal$wrap(top) !== self) return false; // bail"
+    );
+    assert!(!out.sourcemap.is_empty());
+
+    let out = rw
+        .rewrite_handler("}); top.x = 1; ((function(){", flags())
+        .expect("rewrite should succeed");
+    assert_eq!(out.outcome, RewriteOutcome::Passthrough);
+    assert_eq!(out.js, b"}); top.x = 1; ((function(){");
+    assert_eq!(out.errors.len(), 1);
+
+    let out = rw
+        .rewrite_handler("foo(\n  ;", flags())
+        .expect("rewrite should succeed");
+    let span = out.errors[0]
+        .span
+        .as_ref()
+        .expect("error should have a span");
+    assert_eq!((span.start.line, span.start.column), (2, 3));
+}
+
+#[test]
+fn maps_stack_traces_to_original_positions() {
+    let src = "const s = 'é😀';\nfunction f() {\n  return top.x + location.y.z();\n}\nf();\n";
+    let out = NativeRewriter::new()
+        .rewrite(
+            src.as_bytes(),
+            "https://example.com/".to_string(),
+            "https://example.com/app.js".to_string(),
+            Some(false),
+        )
+        .expect("rewrite should succeed");
+    let js = String::from_utf8(out.js).unwrap();
+
+    let mut maps = StackMaps::default();
+    assert!(maps.register(
+        &out.flags.sourcetag,
+        "https://example.com/app.js",
+        &js,
+        &out.sourcemap,
+    ));
+    maps.alias("https://proxy.test/webrascal/app.js", &out.flags.sourcetag);
+    maps.add_runtime("https://proxy.test/webrascal/webrascal.");
+    assert!(!maps.register("broken", "", &js, &out.sourcemap[..5]));
+
+    // 1-based line and UTF-16 column of `needle` in `text`.
+    let position = |text: &str, needle: &str| {
+        let at = text.find(needle).unwrap();
+        let line_start = text[..at].rfind('\n').map_or(0, |i| i + 1);
+        let line = text[..at].matches('\n').count() + 1;
+        let column = text[line_start..at].encode_utf16().count() + 1;
+        (line, column)
+    };
+    let (line, column) = position(&js, ".z()");
+    let (oline, ocolumn) = position(src, ".z()");
+    let (tline, tcolumn) = position(&js, "$webrascal$wrap(top)");
+
+    let stack = format!(
+        "TypeError: z is not a function\n    \
+         at f (https://example.com/app.js:{line}:{column})\n    \
+         at $webrascal$wrap (https://proxy.test/webrascal/webrascal.all.js:1:200)\n    \
+         at https://proxy.test/webrascal/app.js:{tline}:{tcolumn}\n\
+         g@https://example.com/app.js:{line}:{column}\n    \
+         at other (https://example.com/other.js:3:4)"
+    );
+    let (wline, wcolumn) = position(src, "top");
+    assert_eq!(
+        maps.rewrite(&stack),
+        format!(
+            "TypeError: z is not a function\n    \
+             at f (https://example.com/app.js:{oline}:{ocolumn})\n    \
+             at https://example.com/app.js:{wline}:{wcolumn}\n\
+             g@https://example.com/app.js:{oline}:{ocolumn}\n    \
+             at other (https://example.com/other.js:3:4)"
+        )
+    );
+}
+
+#[test]
+fn reconstructs_original_text() {
+    let src = "\u{feff}'use strict';\nfunction f(a) {\n  debugger;\n  return top[a] + location.href;\n}\nf('é');\n";
+    let out = NativeRewriter::new()
+        .rewrite(
+            src.as_bytes(),
+            "https://example.com/".to_string(),
+            "https://example.com/app.js".to_string(),
+            Some(false),
+        )
+        .expect("rewrite should succeed");
+    let js = String::from_utf8(out.js).unwrap();
+    let map = &out.sourcemap;
+    assert_ne!(js, src);
+
+    assert_eq!(transform::original_text(map, 0, &js).as_deref(), Some(src));
+
+    fn function(text: &str) -> (usize, &str) {
+        let start = text.find("function").unwrap();
+        let end = text.find("}\n").unwrap() + 1;
+        (start, &text[start..end])
+    }
+    let (start, rewritten) = function(&js);
+    assert_ne!(rewritten, function(src).1);
+    assert_eq!(
+        transform::original_text(map, start as u32, rewritten).as_deref(),
+        Some(function(src).1)
+    );
+
+    // Starting inside the `$webrascal$wrap(` inserted before `top`.
+    let start = js.find("wrap(top").unwrap();
+    let end = js.find("location").unwrap();
+    assert_eq!(
+        transform::original_text(map, start as u32, &js[start..end]).as_deref(),
+        Some("top[a] + ")
+    );
+    assert_eq!(
+        transform::original_text(&map[..map.len() - 1], 0, &js),
+        None
+    );
+}
@@ -1,37 +1,29 @@
 use oxc::span::Span;
 use std::marker::PhantomData;
 
+pub mod stack;
 pub mod transform;
 
 pub use transform::{
-    Transform, TransformElement, TransformLL, TransformOutput, TransformRecord, TransformType,
+    OriginalPositions, Transform, TransformElement, TransformLL, TransformOutput, TransformRecord,
+    TransformType,
 };
 
-pub struct Transformer<'alloc, 'data, T: Transform<'data>> {
-    pub alloc: Option<&'alloc oxc::allocator::Allocator>,
+pub struct Transformer<'data, T: Transform<'data>> {
     inner: Vec<T>,
     marker: PhantomData<&'data ()>,
 }
 
-impl<'alloc, 'data, T: Transform<'data>> Default for Transformer<'alloc, 'data, T> {
+impl<'data, T: Transform<'data>> Default for Transformer<'data, T> {
     fn default() -> Self {
         Self {
-            alloc: None,
             inner: Vec::new(),
             marker: PhantomData,
         }
     }
 }
 
-impl<'alloc, 'data, T: Transform<'data>> Transformer<'alloc, 'data, T> {
-    pub fn with_alloc(alloc: &'alloc oxc::allocator::Allocator) -> Self {
-        Self {
-            alloc: Some(alloc),
-            inner: Vec::new(),
-            marker: PhantomData,
-        }
-    }
-
+impl<'data, T: Transform<'data>> Transformer<'data, T> {
     pub fn add(&mut self, transform: T) {
         self.inner.push(transform);
     }
@@ -59,10 +51,22 @@ impl<'alloc, 'data, T: Transform<'data>> Transformer<'alloc, 'data, T> {
         &mut self,
         source: &'data str,
         data: &'data T::ToLowLevelData,
-    ) -> TransformOutput {
+    ) -> TransformOutput<'data> {
         self.inner.sort();
 
-        let mut out = Vec::with_capacity(source.len() + 128);
+        let mut size = source.len();
+        let mut cursor: usize = 0;
+        for transform in &self.inner {
+            let span = transform.span();
+            let (start, end) = (span.start as usize, span.end as usize);
+            if start < cursor || end < start || end > source.len() {
+                continue;
+            }
+            size = size + transform.output_len(data) - (end - start);
+            cursor = end;
+        }
+
+        let mut out = Vec::with_capacity(size);
         let mut cursor: usize = 0;
         let mut offset: i32 = 0;
         let mut records = Vec::with_capacity(self.inner.len());
@@ -79,57 +83,36 @@ impl<'alloc, 'data, T: Transform<'data>> Transformer<'alloc, 'data, T> {
 
             let ll = transform.into_low_level(data, offset);
             let output_pos = out.len() as u32;
-            let mut rendered = Vec::new();
-            for item in ll.change {
-                match item {
-                    TransformElement::Str(s) => rendered.extend_from_slice(s.as_bytes()),
-                    TransformElement::Owned(s) => rendered.extend_from_slice(s.as_bytes()),
-                    TransformElement::U32(v) => rendered.extend_from_slice(v.to_string().as_bytes()),
-                }
+            for item in &ll.change {
+                item.render(&mut out);
             }
+            let rendered = out.len() as u32 - output_pos;
 
-            match ll.ty {
-                TransformType::Insert => {
-                    out.extend_from_slice(&rendered);
+            let original = match ll.ty {
+                TransformType::Insert | TransformType::Inject => {
                     out.extend_from_slice(&source.as_bytes()[start..end]);
-                    offset += rendered.len() as i32;
-                    records.push(TransformRecord {
-                        output_pos,
-                        size: rendered.len() as u32,
-                        ty: TransformType::Insert,
-                        original: Vec::new(),
-                    });
+                    offset += rendered as i32;
+                    &[][..]
                 }
                 TransformType::Replace => {
-                    let original = source.as_bytes()[start..end].to_vec();
-                    out.extend_from_slice(&rendered);
-                    let old_len = (end - start) as i32;
-                    offset += rendered.len() as i32 - old_len;
-                    records.push(TransformRecord {
-                        output_pos,
-                        size: rendered.len() as u32,
-                        ty: TransformType::Replace,
-                        original,
-                    });
+                    offset += rendered as i32 - (end - start) as i32;
+                    &source.as_bytes()[start..end]
                 }
-            }
+            };
+            records.push(TransformRecord {
+                output_pos,
+                size: rendered,
+                ty: ll.ty,
+                original,
+            });
 
             cursor = end;
         }
 
         out.extend_from_slice(&source.as_bytes()[cursor..]);
+        debug_assert_eq!(out.len(), size);
 
-        let mut map = Vec::with_capacity(8 + records.len() * 16);
-        map.extend_from_slice(&(records.len() as u32).to_le_bytes());
-        for record in &records {
-            map.extend_from_slice(&record.output_pos.to_le_bytes());
-            map.extend_from_slice(&record.size.to_le_bytes());
-            map.push(record.ty as u8);
-            if matches!(record.ty, TransformType::Replace) {
-                map.extend_from_slice(&(record.original.len() as u32).to_le_bytes());
-                map.extend_from_slice(&record.original);
-            }
-        }
+        let map = encode_map(&records);
 
         TransformOutput {
             output: out,
@@ -138,3 +121,106 @@ impl<'alloc, 'data, T: Transform<'data>> Transformer<'alloc, 'data, T> {
         }
     }
 }
+
+/// Encoded size of an insert or inject record.
+pub const INSERT_RECORD_LEN: usize = 9;
+
+pub fn encode_map(records: &[TransformRecord<'_>]) -> Vec<u8> {
+    let mut map = Vec::with_capacity(encoded_map_len(records));
+    map.extend_from_slice(&(records.len() as u32).to_le_bytes());
+    for record in records {
+        map.extend_from_slice(&record.output_pos.to_le_bytes());
+        map.extend_from_slice(&record.size.to_le_bytes());
+        map.push(record.ty as u8);
+        if matches!(record.ty, TransformType::Replace) {
+            map.extend_from_slice(&(record.original.len() as u32).to_le_bytes());
+            map.extend_from_slice(record.original);
+        }
+    }
+    map
+}
+
+/// Reads back a map made by [`encode_map`], borrowing replaced text from it.
+/// Gives `None` for a truncated or malformed map.
+pub fn decode_map(map: &[u8]) -> Option<Vec<TransformRecord<'_>>> {
+    fn take<'a>(map: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        let (head, rest) = map.split_at_checked(n)?;
+        *map = rest;
+        Some(head)
+    }
+    fn u32(map: &mut &[u8]) -> Option<u32> {
+        Some(u32::from_le_bytes(take(map, 4)?.try_into().ok()?))
+    }
+
+    let mut map = map;
+    let count = u32(&mut map)?;
+    let mut records = Vec::with_capacity((count as usize).min(map.len() / INSERT_RECORD_LEN));
+    for _ in 0..count {
+        let output_pos = u32(&mut map)?;
+        let size = u32(&mut map)?;
+        let (ty, original) = match take(&mut map, 1)?[0] {
+            0 => (TransformType::Insert, &[][..]),
+            2 => (TransformType::Inject, &[][..]),
+            1 => {
+                let len = u32(&mut map)?;
+                (TransformType::Replace, take(&mut map, len as usize)?)
+            }
+            _ => return None,
+        };
+        records.push(TransformRecord {
+            output_pos,
+            size,
+            ty,
+            original,
+        });
+    }
+    map.is_empty().then_some(records)
+}
+
+/// The source text that `output`, the part of a rewritten script starting at
+/// `start`, was rewritten from, such as the original text of a function
+/// given its rewritten text. `map` is the binary map of the whole script.
+/// A change cut off by the start of the range counts in full and one cut off
+/// by its end not at all, so a range that covers whole tokens gives back
+/// exactly their source. Gives `None` for a malformed map.
+pub fn original_text(map: &[u8], start: u32, output: &str) -> Option<String> {
+    Some(reconstruct(&decode_map(map)?, start, output))
+}
+
+/// [`original_text`] with the map already decoded.
+pub(crate) fn reconstruct(records: &[TransformRecord<'_>], start: u32, output: &str) -> String {
+    let end = start + output.len() as u32;
+    let output = output.as_bytes();
+    let mut out = Vec::with_capacity(output.len());
+    let mut cursor = start;
+    for record in records {
+        let (pos, record_end) = (record.output_pos, record.output_pos + record.size);
+        if record_end < start || (pos < start && record_end == start) {
+            continue;
+        }
+        if pos > end || (pos >= start && record_end > end) {
+            break;
+        }
+        let from = (cursor - start) as usize;
+        let to = (pos.max(cursor) - start) as usize;
+        out.extend_from_slice(&output[from..to]);
+        if record.ty == TransformType::Replace {
+            out.extend_from_slice(record.original);
+        }
+        cursor = record_end.clamp(cursor, end);
+    }
+    out.extend_from_slice(&output[(cursor - start) as usize..]);
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Size of the map [`encode_map`] produces for `records`. Positions are fixed
+/// width, so this does not depend on where the records are.
+pub fn encoded_map_len(records: &[TransformRecord<'_>]) -> usize {
+    4 + records
+        .iter()
+        .map(|r| match r.ty {
+            TransformType::Insert | TransformType::Inject => INSERT_RECORD_LEN,
+            TransformType::Replace => INSERT_RECORD_LEN + 4 + r.original.len(),
+        })
+        .sum::<usize>()
+}
@@ -0,0 +1,207 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{OriginalPositions, decode_map, reconstruct};
+
+/// Maps stack traces from rewritten scripts back to the scripts they were
+/// rewritten from, using the binary maps the scripts registered.
+#[derive(Debug, Default)]
+pub struct StackMaps {
+    /// By source tag.
+    scripts: HashMap<String, StackScript>,
+    /// Source tag of the script behind each URL frames may show.
+    urls: HashMap<String, String>,
+    runtime: Vec<String>,
+}
+
+#[derive(Debug)]
+struct StackScript {
+    url: String,
+    output: Lines,
+    original: Lines,
+    positions: OriginalPositions,
+}
+
+impl StackMaps {
+    /// Registers the map of the script tagged `tag`. `output` is its
+    /// rewritten text, which the map alone can't place lines in, and `url`
+    /// its URL before it was proxied, which its frames show through its
+    /// `sourceURL`. Gives false if `map` doesn't decode.
+    pub fn register(&mut self, tag: &str, url: &str, output: &str, map: &[u8]) -> bool {
+        let Some(records) = decode_map(map) else {
+            return false;
+        };
+        let script = StackScript {
+            url: url.to_string(),
+            output: Lines::new(output),
+            original: Lines::new(&reconstruct(&records, 0, output)),
+            positions: OriginalPositions::new(&records),
+        };
+        if !url.is_empty() {
+            self.urls.insert(url.to_string(), tag.to_string());
+        }
+        self.scripts.insert(tag.to_string(), script);
+        true
+    }
+
+    /// Frames at `url`, such as the proxied URL of a script without a
+    /// `sourceURL`, are in the script registered as `tag`.
+    pub fn alias(&mut self, url: &str, tag: &str) {
+        self.urls.insert(url.to_string(), tag.to_string());
+    }
+
+    /// Frames whose URL starts with `prefix` belong to the proxy runtime and
+    /// are dropped.
+    pub fn add_runtime(&mut self, prefix: &str) {
+        self.runtime.push(prefix.to_string());
+    }
+
+    /// Rewrites the location of every frame of `stack` in a registered script
+    /// to where it is in the original script, and drops runtime frames and
+    /// frames in code injected into the script, which has no place there. Both
+    /// V8's `at f (url:1:2)` and Firefox's `f@url:1:2` frames are understood;
+    /// other lines are kept as they are.
+    pub fn rewrite(&self, stack: &str) -> String {
+        let mut out = String::with_capacity(stack.len());
+        for frame in stack.split_inclusive('\n') {
+            let Some(loc) = location(frame) else {
+                out.push_str(frame);
+                continue;
+            };
+            let url = &frame[loc.start..loc.url_end];
+            if self.runtime.iter().any(|p| url.starts_with(p.as_str())) {
+                continue;
+            }
+            let (url, line, column) = match self.locate(url, loc.line, loc.column) {
+                Some(Some(located)) => located,
+                Some(None) => continue,
+                None => {
+                    out.push_str(frame);
+                    continue;
+                }
+            };
+            out.push_str(&frame[..loc.start]);
+            let _ = write!(out, "{url}:{line}:{column}");
+            out.push_str(&frame[loc.end..]);
+        }
+        out
+    }
+
+    /// Where 1-based `line` and UTF-16 `column` of the script at `url` were
+    /// before the rewrite, or `Some(None)` if they are in injected code.
+    fn locate<'a>(
+        &'a self,
+        url: &'a str,
+        line: u32,
+        column: u32,
+    ) -> Option<Option<(&'a str, u32, u32)>> {
+        let script = self.scripts.get(self.urls.get(url)?)?;
+        let output = script.output.offset(line, column)?;
+        if script.positions.injected(output) {
+            return Some(None);
+        }
+        let (line, column) = script.original.position(script.positions.original(output));
+        let url = if script.url.is_empty() {
+            url
+        } else {
+            &script.url
+        };
+        Some(Some((url, line, column)))
+    }
+}
+
+/// Where the `url:line:column` of a frame is.
+struct Location {
+    start: usize,
+    url_end: usize,
+    end: usize,
+    line: u32,
+    column: u32,
+}
+
+/// Finds the location in `at f (url:1:2)`, `at url:1:2` or `f@url:1:2`.
+fn location(frame: &str) -> Option<Location> {
+    let frame = frame.trim_end();
+    let frame = frame.strip_suffix(')').unwrap_or(frame);
+    let number = |s: &str| {
+        (!s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| s.parse().ok())
+            .flatten()
+    };
+    let (rest, column) = frame.rsplit_once(':')?;
+    let (url, line) = rest.rsplit_once(':')?;
+    let start = url.rfind(['(', '@', ' ']).map_or(0, |i| i + 1);
+    (start < url.len()).then_some(Location {
+        start,
+        url_end: url.len(),
+        end: frame.len(),
+        line: number(line)?,
+        column: number(column)?,
+    })
+}
+
+/// Line starts of a text, and its characters that take fewer UTF-16 units
+/// than UTF-8 bytes, for converting between byte offsets and the 1-based
+/// line and UTF-16 column that stack frames give.
+#[derive(Debug)]
+struct Lines {
+    len: u32,
+    starts: Vec<u32>,
+    /// Offset of each such character and how many bytes more it takes.
+    wide: Vec<(u32, u32)>,
+}
+
+impl Lines {
+    fn new(text: &str) -> Self {
+        let mut starts = vec![0];
+        let mut wide = Vec::new();
+        let mut chars = text.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let extra = c.len_utf8() - c.len_utf16();
+            if extra > 0 {
+                wide.push((i as u32, extra as u32));
+            }
+            match c {
+                '\r' if matches!(chars.peek(), Some((_, '\n'))) => {}
+                '\n' | '\r' | '\u{2028}' | '\u{2029}' => starts.push((i + c.len_utf8()) as u32),
+                _ => {}
+            }
+        }
+        Self {
+            len: text.len() as u32,
+            starts,
+            wide,
+        }
+    }
+
+    fn line_end(&self, line: usize) -> u32 {
+        self.starts.get(line + 1).map_or(self.len, |&e| e)
+    }
+
+    fn offset(&self, line: u32, column: u32) -> Option<u32> {
+        let line = line.checked_sub(1)? as usize;
+        let start = *self.starts.get(line)?;
+        let end = self.line_end(line);
+        let mut offset = start + column.saturating_sub(1);
+        let first = self.wide.partition_point(|&(o, _)| o < start);
+        for &(o, extra) in &self.wide[first..] {
+            if o >= offset || o >= end {
+                break;
+            }
+            offset += extra;
+        }
+        Some(offset.min(end))
+    }
+
+    fn position(&self, offset: u32) -> (u32, u32) {
+        let offset = offset.min(self.len);
+        let line = self.starts.partition_point(|&s| s <= offset) - 1;
+        let start = self.starts[line];
+        let first = self.wide.partition_point(|&(o, _)| o < start);
+        let extra: u32 = self.wide[first..]
+            .iter()
+            .take_while(|&&(o, _)| o < offset)
+            .map(|&(_, e)| e)
+            .sum();
+        (line as u32 + 1, (offset - start).saturating_sub(extra) + 1)
+    }
+}
@@ -6,6 +6,10 @@ use smallvec::SmallVec;
 pub enum TransformType {
     Insert = 0,
     Replace = 1,
+    /// An insert of code that stands for nothing in the source, such as an
+    /// init script or the sourcemap registration. Offsets in it map to where
+    /// it was inserted, but it is no part of the script's own code.
+    Inject = 2,
 }
 
 #[derive(Debug, Clone)]
@@ -21,23 +25,190 @@ pub enum TransformElement<'a> {
     U32(u32),
 }
 
+impl TransformElement<'_> {
+    /// Rendered length in bytes.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Str(s) => s.len(),
+            Self::Owned(s) => s.len(),
+            Self::U32(v) => v.checked_ilog10().unwrap_or(0) as usize + 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn render(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Str(s) => out.extend_from_slice(s.as_bytes()),
+            Self::Owned(s) => out.extend_from_slice(s.as_bytes()),
+            Self::U32(v) => {
+                let mut digits = [0u8; 10];
+                let mut v = *v;
+                let mut i = digits.len();
+                loop {
+                    i -= 1;
+                    digits[i] = b'0' + (v % 10) as u8;
+                    v /= 10;
+                    if v == 0 {
+                        break;
+                    }
+                }
+                out.extend_from_slice(&digits[i..]);
+            }
+        }
+    }
+}
+
+impl<'a> From<&'a str> for TransformElement<'a> {
+    fn from(s: &'a str) -> Self {
+        Self::Str(s)
+    }
+}
+
+impl<'a> From<&'a String> for TransformElement<'a> {
+    fn from(s: &'a String) -> Self {
+        Self::Str(s)
+    }
+}
+
+impl From<String> for TransformElement<'_> {
+    fn from(s: String) -> Self {
+        Self::Owned(s)
+    }
+}
+
+impl From<u32> for TransformElement<'_> {
+    fn from(v: u32) -> Self {
+        Self::U32(v)
+    }
+}
+
 pub trait Transform<'a>: Ord {
     type ToLowLevelData: 'a;
     fn span(&self) -> Span;
+    /// How many bytes of output the change and its span turn into, so the
+    /// output can be allocated at its final size up front.
+    fn output_len(&self, data: &Self::ToLowLevelData) -> usize;
     fn into_low_level(self, data: &Self::ToLowLevelData, offset: i32) -> TransformLL<'a>;
 }
 
 #[derive(Debug, Clone)]
-pub struct TransformRecord {
+pub struct TransformRecord<'a> {
     pub output_pos: u32,
     pub size: u32,
     pub ty: TransformType,
-    pub original: Vec<u8>,
+    /// What a replace replaced, borrowed from the source.
+    pub original: &'a [u8],
 }
 
 #[derive(Debug, Clone)]
-pub struct TransformOutput {
+pub struct TransformOutput<'a> {
     pub output: Vec<u8>,
     pub sourcemap: Vec<u8>,
-    pub records: Vec<TransformRecord>,
+    pub records: Vec<TransformRecord<'a>>,
+}
+
+impl TransformOutput<'_> {
+    /// Maps an offset in the source to the output, placed before any change
+    /// made at that offset.
+    pub fn output_pos(&self, original: u32) -> u32 {
+        let mut delta: i64 = 0;
+        for record in &self.records {
+            let start = record.output_pos as i64 - delta;
+            if start >= original as i64 {
+                break;
+            }
+            delta += match record.ty {
+                TransformType::Insert | TransformType::Inject => record.size as i64,
+                TransformType::Replace => record.size as i64 - record.original.len() as i64,
+            };
+        }
+        (original as i64 + delta) as u32
+    }
+
+    /// An index for mapping output offsets back to the source.
+    pub fn original_positions(&self) -> OriginalPositions {
+        OriginalPositions::new(&self.records)
+    }
+
+    /// Injects `text` at output offset `pos` and records it, keeping the
+    /// records in order. The sourcemap is not re-encoded.
+    pub fn splice(&mut self, pos: u32, text: &[u8]) {
+        let size = text.len() as u32;
+        self.output
+            .splice(pos as usize..pos as usize, text.iter().copied());
+
+        let idx = self.records.partition_point(|r| r.output_pos < pos);
+        for record in &mut self.records[idx..] {
+            record.output_pos += size;
+        }
+        self.records.insert(
+            idx,
+            TransformRecord {
+                output_pos: pos,
+                size,
+                ty: TransformType::Inject,
+                original: &[],
+            },
+        );
+    }
+}
+
+/// Maps output offsets to source offsets, built by
+/// [`TransformOutput::original_positions`].
+#[derive(Debug, Clone)]
+pub struct OriginalPositions {
+    /// Output range of each record, with the size change before it.
+    spans: Vec<(u32, u32, i64)>,
+    delta: i64,
+    /// Output ranges of [`TransformType::Inject`] records.
+    injected: Vec<(u32, u32)>,
+}
+
+impl OriginalPositions {
+    /// `records` must be in output order, as [`TransformOutput::records`] and
+    /// [`crate::decode_map`] give them.
+    pub fn new(records: &[TransformRecord<'_>]) -> Self {
+        let mut delta = 0;
+        let mut spans = Vec::with_capacity(records.len());
+        let mut injected = Vec::new();
+        for record in records {
+            let before = delta;
+            let end = record.output_pos + record.size;
+            delta += record.size as i64 - record.original.len() as i64;
+            spans.push((record.output_pos, end, before));
+            if record.ty == TransformType::Inject {
+                injected.push((record.output_pos, end));
+            }
+        }
+        Self {
+            spans,
+            delta,
+            injected,
+        }
+    }
+
+    /// Whether `output` is in injected code, which comes from no part of the
+    /// source.
+    pub fn injected(&self, output: u32) -> bool {
+        let idx = self.injected.partition_point(|&(start, _)| start <= output);
+        idx.checked_sub(1)
+            .is_some_and(|i| output < self.injected[i].1)
+    }
+
+    /// Offsets inside a change map to where the change was made; others to
+    /// the source text they were copied from.
+    pub fn original(&self, output: u32) -> u32 {
+        let idx = self.spans.partition_point(|&(start, _, _)| start <= output);
+        let Some(&(start, end, before)) = idx.checked_sub(1).map(|i| &self.spans[i]) else {
+            return output;
+        };
+        if output < end {
+            return (start as i64 - before) as u32;
+        }
+        let after = self.spans.get(idx).map_or(self.delta, |&(_, _, d)| d);
+        (output as i64 - after) as u32
+    }
 }
@@ -1,6 +1,10 @@
+use js::error::RewriteError;
+use js_sys::{Array, Reflect};
 use thiserror::Error;
 use wasm_bindgen::JsValue;
 
+use crate::jsr::diagnostic_object;
+
 #[derive(Debug, Error)]
 pub enum WasmRewriterError {
     #[error("{0}")]
@@ -17,4 +21,29 @@ impl From<WasmRewriterError> for JsValue {
     fn from(value: WasmRewriterError) -> Self {
         JsValue::from_str(&value.to_string())
     }
-}
\ No newline at end of file
+}
+
+/// Turns a failed rewrite into a JS `Error`. A script rejected by the
+/// invalid-JS policy gets `kind: "invalid-js"` and its diagnostics as
+/// `errors`, and one rejected by the budget policy `kind: "over-budget"` and
+/// the budget it went over as `budget`, so the caller can tell a rejection
+/// from the rewriter failing.
+pub fn rewrite_error_value(err: anyhow::Error) -> JsValue {
+    let value = js_sys::Error::new(&err.to_string());
+    match err.downcast_ref() {
+        Some(RewriteError::InvalidJs { errors }) => {
+            let list = Array::new();
+            for e in errors {
+                list.push(&diagnostic_object(e));
+            }
+            Reflect::set(&value, &"kind".into(), &"invalid-js".into()).ok();
+            Reflect::set(&value, &"errors".into(), &list).ok();
+        }
+        Some(RewriteError::OverBudget { budget }) => {
+            Reflect::set(&value, &"kind".into(), &"over-budget".into()).ok();
+            Reflect::set(&value, &"budget".into(), &budget.as_str().into()).ok();
+        }
+        _ => {}
+    }
+    value.into()
+}
@@ -1,4 +1,10 @@
-use js_sys::{Object, Uint8Array};
+use js::{
+    audit::Finding,
+    diagnostic::{Diagnostic, Label, LineIndex, Position},
+    pretty::Pretty,
+    stats::{Provenance, RewriteStats},
+};
+use js_sys::{Array, Object, Reflect, Uint8Array};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -7,19 +13,47 @@ pub struct JsRewriterOutput {
     map: Uint8Array,
     rascaltag: String,
     errors: Array,
+    module: bool,
+    outcome: String,
+    applied_rules: Array,
+    injected_scripts: Array,
+    stats: Object,
+    provenance: Array,
+    source_encoding: String,
+    pretty: JsValue,
 }
 
-use js_sys::Array;
-
 #[wasm_bindgen]
 impl JsRewriterOutput {
     #[wasm_bindgen(constructor)]
-    pub fn new(js: Uint8Array, map: Uint8Array, rascaltag: String, errors: Array) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        js: Uint8Array,
+        map: Uint8Array,
+        rascaltag: String,
+        errors: Array,
+        module: bool,
+        outcome: String,
+        applied_rules: Array,
+        injected_scripts: Array,
+        stats: Object,
+        provenance: Array,
+        source_encoding: String,
+        pretty: JsValue,
+    ) -> Self {
         Self {
             js,
             map,
             rascaltag,
             errors,
+            module,
+            outcome,
+            applied_rules,
+            injected_scripts,
+            stats,
+            provenance,
+            source_encoding,
+            pretty,
         }
     }
 
@@ -43,12 +77,183 @@ impl JsRewriterOutput {
         self.errors.clone()
     }
 
+    #[wasm_bindgen(getter)]
+    pub fn module(&self) -> bool {
+        self.module
+    }
+
+    /// `rewritten`, `best-effort`, `passthrough`, `prefiltered` or
+    /// `over-budget`.
+    #[wasm_bindgen(getter)]
+    pub fn outcome(&self) -> String {
+        self.outcome.clone()
+    }
+
+    /// Names of the patch rules that changed something.
+    #[wasm_bindgen(getter, js_name = appliedRules)]
+    pub fn applied_rules(&self) -> Array {
+        self.applied_rules.clone()
+    }
+
+    /// Names of the init scripts inserted.
+    #[wasm_bindgen(getter, js_name = injectedScripts)]
+    pub fn injected_scripts(&self) -> Array {
+        self.injected_scripts.clone()
+    }
+
+    /// Rewrite counts by kind, timings in milliseconds and sizes in bytes.
+    #[wasm_bindgen(getter)]
+    pub fn stats(&self) -> Object {
+        self.stats.clone()
+    }
+
+    /// Empty unless provenance was enabled on the rewriter.
+    #[wasm_bindgen(getter)]
+    pub fn provenance(&self) -> Array {
+        self.provenance.clone()
+    }
+
+    /// The encoding the input was decoded as.
+    #[wasm_bindgen(getter, js_name = sourceEncoding)]
+    pub fn source_encoding(&self) -> String {
+        self.source_encoding.clone()
+    }
+
+    /// `{ code, map }` with the output formatted and a source map back to
+    /// the input, when pretty output was enabled on the rewriter.
+    #[wasm_bindgen(getter)]
+    pub fn pretty(&self) -> JsValue {
+        self.pretty.clone()
+    }
+
+    /// The charset to serve `js` with, which is always `utf-8`.
+    #[wasm_bindgen(getter)]
+    pub fn charset(&self) -> String {
+        "utf-8".to_string()
+    }
+
     pub fn as_object(&self) -> Object {
         let o = Object::new();
-        js_sys::Reflect::set(&o, &"js".into(), &self.js.clone().into()).ok();
-        js_sys::Reflect::set(&o, &"map".into(), &self.map.clone().into()).ok();
-        js_sys::Reflect::set(&o, &"rascaltag".into(), &self.rascaltag.clone().into()).ok();
-        js_sys::Reflect::set(&o, &"errors".into(), &self.errors.clone().into()).ok();
+        set(&o, "js", self.js.clone().into());
+        set(&o, "map", self.map.clone().into());
+        set(&o, "rascaltag", self.rascaltag.clone().into());
+        set(&o, "errors", self.errors.clone().into());
+        set(&o, "module", self.module.into());
+        set(&o, "outcome", self.outcome.clone().into());
+        set(&o, "appliedRules", self.applied_rules.clone().into());
+        set(&o, "injectedScripts", self.injected_scripts.clone().into());
+        set(&o, "stats", self.stats.clone().into());
+        set(&o, "provenance", self.provenance.clone().into());
+        set(&o, "sourceEncoding", self.source_encoding.clone().into());
+        set(&o, "charset", "utf-8".into());
+        set(&o, "pretty", self.pretty.clone());
         o
     }
 }
+
+fn set(o: &Object, key: &str, value: JsValue) {
+    Reflect::set(o, &key.into(), &value).ok();
+}
+
+fn position_object(p: &Position) -> Object {
+    let o = Object::new();
+    set(&o, "offset", p.offset.into());
+    set(&o, "line", p.line.into());
+    set(&o, "column", p.column.into());
+    o
+}
+
+fn label_object(l: &Label) -> Object {
+    let o = Object::new();
+    set(&o, "start", position_object(&l.start).into());
+    set(&o, "end", position_object(&l.end).into());
+    set(
+        &o,
+        "message",
+        l.message.as_deref().map_or(JsValue::NULL, JsValue::from),
+    );
+    o
+}
+
+pub fn diagnostic_object(d: &Diagnostic) -> Object {
+    let o = Object::new();
+    set(&o, "message", d.message.as_str().into());
+    set(&o, "severity", d.severity.as_str().into());
+    set(&o, "code", d.code.as_str().into());
+    set(&o, "url", d.url.as_str().into());
+    set(
+        &o,
+        "span",
+        d.span
+            .as_ref()
+            .map_or(JsValue::NULL, |l| label_object(l).into()),
+    );
+    let labels = Array::new();
+    for label in &d.labels {
+        labels.push(&label_object(label));
+    }
+    set(&o, "labels", labels.into());
+    set(
+        &o,
+        "help",
+        d.help.as_deref().map_or(JsValue::NULL, JsValue::from),
+    );
+    o
+}
+
+pub fn stats_object(s: &RewriteStats) -> Object {
+    let counts = Object::new();
+    for (kind, count) in &s.counts {
+        set(&counts, kind, (*count).into());
+    }
+    let o = Object::new();
+    set(&o, "counts", counts.into());
+    set(&o, "prefilter", (s.prefilter.as_secs_f64() * 1000.0).into());
+    set(&o, "parse", (s.parse.as_secs_f64() * 1000.0).into());
+    set(&o, "visit", (s.visit.as_secs_f64() * 1000.0).into());
+    set(&o, "transform", (s.transform.as_secs_f64() * 1000.0).into());
+    set(&o, "inputSize", (s.input_size as f64).into());
+    set(&o, "outputSize", (s.output_size as f64).into());
+    o
+}
+
+pub fn provenance_object(p: &Provenance, lines: &LineIndex) -> Object {
+    let o = Object::new();
+    set(&o, "kind", p.kind.into());
+    set(
+        &o,
+        "start",
+        position_object(&lines.position(p.span.start)).into(),
+    );
+    set(
+        &o,
+        "end",
+        position_object(&lines.position(p.span.end)).into(),
+    );
+    set(&o, "source", p.source.as_str().into());
+    o
+}
+
+pub fn finding_object(f: &Finding, lines: &LineIndex) -> Object {
+    let o = Object::new();
+    set(&o, "category", f.category.as_str().into());
+    set(&o, "description", f.category.description().into());
+    set(
+        &o,
+        "start",
+        position_object(&lines.position(f.span.start)).into(),
+    );
+    set(
+        &o,
+        "end",
+        position_object(&lines.position(f.span.end)).into(),
+    );
+    o
+}
+
+pub fn pretty_object(p: &Pretty) -> Object {
+    let o = Object::new();
+    set(&o, "code", p.code.as_str().into());
+    set(&o, "map", p.map.as_str().into());
+    o
+}
@@ -1,17 +1,21 @@
 mod error;
 mod jsr;
 
-use std::error::Error;
+use std::{error::Error, time::Duration};
 
 use js::{
-    Rewriter as JsRewriter,
-    cfg::{Config, Flags, StringBuilder, UrlRewriter},
+    RewriteOutcome, Rewriter as JsRewriter,
+    budget::BudgetPolicy,
+    cfg::{Config, Flags, InvalidJsPolicy, StringBuilder, UrlDestination, UrlRewriter},
+    charset,
+    diagnostic::LineIndex,
 };
-use js_sys::{Array, Function, Object, Reflect, Uint8Array};
+use js_sys::{Array, Date, Function, JSON, Object, Reflect, Uint8Array};
+use transform::stack::StackMaps;
 use wasm_bindgen::prelude::*;
 use web_sys::Url;
 
-use crate::error::WasmRewriterError;
+use crate::error::{WasmRewriterError, rewrite_error_value};
 pub use crate::jsr::JsRewriterOutput;
 
 struct WasmUrlRewriter {
@@ -19,7 +23,9 @@ struct WasmUrlRewriter {
 }
 
 // This object only exists in the single-threaded wasm runtime.
+#[allow(unsafe_code)]
 unsafe impl Send for WasmUrlRewriter {}
+#[allow(unsafe_code)]
 unsafe impl Sync for WasmUrlRewriter {}
 
 impl UrlRewriter for WasmUrlRewriter {
@@ -29,7 +35,8 @@ impl UrlRewriter for WasmUrlRewriter {
         flags: &Flags,
         url: &str,
         builder: &mut StringBuilder,
-        _module: bool,
+        dest: UrlDestination,
+        module: bool,
     ) -> Result<(), Box<dyn Error + Sync + Send>> {
         let resolved = Url::new_with_base(url, &flags.base)
             .map_err(|_| WasmRewriterError::Msg(format!("failed to resolve url: {url}")))?;
@@ -53,6 +60,7 @@ impl UrlRewriter for WasmUrlRewriter {
 
         builder.push_str(&cfg.prefix);
         builder.push_str(&encoded);
+        dest.push_query(module, builder);
         Ok(())
     }
 }
@@ -61,6 +69,20 @@ impl UrlRewriter for WasmUrlRewriter {
 pub struct Rewriter {
     js: JsRewriter<WasmUrlRewriter>,
     webrascal: Object,
+    invalid_js: InvalidJsPolicy,
+    provenance: bool,
+    pretty: bool,
+    budget: Budget,
+}
+
+/// The `budget` object of the config.
+#[derive(Default)]
+struct Budget {
+    max_input_size: Option<usize>,
+    max_changes: Option<usize>,
+    time: Option<Duration>,
+    max_steps: Option<u64>,
+    policy: BudgetPolicy,
 }
 
 #[wasm_bindgen]
@@ -68,51 +90,119 @@ impl Rewriter {
     #[wasm_bindgen(constructor)]
     pub fn new(webrascal: Object) -> Result<Rewriter, JsValue> {
         let cfg = config_from_object(&webrascal).unwrap_or_default();
+        let invalid_js = match read_flag(&webrascal, "allowInvalidJs") {
+            Some(false) => InvalidJsPolicy::Reject,
+            _ => InvalidJsPolicy::BestEffort,
+        };
+        let mut js = JsRewriter::new(
+            cfg,
+            WasmUrlRewriter {
+                webrascal: webrascal.clone(),
+            },
+        );
+        js.set_clock(|| Duration::from_secs_f64(Date::now() / 1000.0));
+        if let Some(rules) = json_field(&webrascal, "patchRules") {
+            js.load_patch_rules(&rules)
+                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        }
+        if let Some(scripts) = json_field(&webrascal, "initScripts") {
+            js.load_init_scripts(&scripts)
+                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        }
+        let budget = budget_from_object(&webrascal)?;
         Ok(Self {
-            js: JsRewriter::new(
-                cfg,
-                WasmUrlRewriter {
-                    webrascal: webrascal.clone(),
-                },
-            ),
+            js,
             webrascal,
+            invalid_js,
+            provenance: false,
+            pretty: false,
+            budget,
         })
     }
 
+    /// Overrides the policy derived from `flags.allowInvalidJs`: one of
+    /// `passthrough`, `best-effort` or `reject`.
+    pub fn set_invalid_js(&mut self, policy: String) -> Result<(), JsValue> {
+        self.invalid_js = policy.parse().map_err(|e: String| JsValue::from_str(&e))?;
+        Ok(())
+    }
+
+    /// Record which visitor, pass or rule produced each rewrite in the
+    /// output's `provenance`.
+    pub fn set_provenance(&mut self, enabled: bool) {
+        self.provenance = enabled;
+    }
+
+    /// Also format the output for reading, in the output's `pretty`.
+    pub fn set_pretty(&mut self, enabled: bool) {
+        self.pretty = enabled;
+    }
+
     pub fn rewrite_js(
         &mut self,
         js: String,
         base: String,
         url: String,
-        module: bool,
+        module: Option<bool>,
+        salt: Option<String>,
     ) -> Result<JsRewriterOutput, JsValue> {
-        self.rewrite_js_bytes(js.into_bytes(), base, url, module)
+        self.rewrite_js_bytes(js.into_bytes(), base, url, module, salt, None)
     }
 
+    /// `charset` is the label from the `Content-Type` or `<script charset>`;
+    /// a BOM overrides it and UTF-8 is the fallback.
     pub fn rewrite_js_bytes(
         &mut self,
         js: Vec<u8>,
         base: String,
-        _url: String,
-        module: bool,
+        url: String,
+        module: Option<bool>,
+        salt: Option<String>,
+        charset: Option<String>,
     ) -> Result<JsRewriterOutput, JsValue> {
         let flags = Flags {
             base,
-            is_module: module,
-            sourcetag: random_tag(),
+            url,
+            is_module: module.into(),
+            tag_salt: salt.unwrap_or_default(),
+            invalid_js: self.invalid_js,
+            charset,
+            provenance: self.provenance,
+            pretty: self.pretty,
+            max_input_size: self.budget.max_input_size,
+            max_changes: self.budget.max_changes,
+            time_budget: self.budget.time,
+            max_steps: self.budget.max_steps,
+            over_budget: self.budget.policy,
             ..Flags::default()
         };
 
         let rewritten = self
             .js
             .rewrite_bytes(&js, flags)
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+            .map_err(rewrite_error_value)?;
 
         let js_out = Uint8Array::from(rewritten.js.as_slice());
         let map_out = Uint8Array::from(rewritten.sourcemap.as_slice());
         let errs = Array::new();
         for err in rewritten.errors {
-            errs.push(&JsValue::from_str(&err));
+            errs.push(&jsr::diagnostic_object(&err));
+        }
+        let applied = Array::new();
+        for rule in rewritten.applied_rules {
+            applied.push(&JsValue::from_str(&rule));
+        }
+        let injected = Array::new();
+        for script in rewritten.injected_scripts {
+            injected.push(&JsValue::from_str(&script));
+        }
+        let provenance = Array::new();
+        if !rewritten.provenance.is_empty() {
+            let decoded = charset::decode(&js, rewritten.flags.charset.as_deref());
+            let lines = LineIndex::new(&decoded.text);
+            for p in &rewritten.provenance {
+                provenance.push(&jsr::provenance_object(p, &lines));
+            }
         }
 
         Ok(JsRewriterOutput::new(
@@ -120,15 +210,158 @@ impl Rewriter {
             map_out,
             rewritten.flags.sourcetag,
             errs,
+            rewritten.kind.is_module(),
+            match rewritten.outcome {
+                RewriteOutcome::Rewritten => "rewritten",
+                RewriteOutcome::BestEffort => "best-effort",
+                RewriteOutcome::Passthrough => "passthrough",
+                RewriteOutcome::Prefiltered => "prefiltered",
+                RewriteOutcome::OverBudget(_) => "over-budget",
+            }
+            .to_string(),
+            applied,
+            injected,
+            jsr::stats_object(&rewritten.stats),
+            provenance,
+            rewritten.source_encoding.to_string(),
+            rewritten
+                .pretty
+                .as_ref()
+                .map_or(JsValue::NULL, |p| jsr::pretty_object(p).into()),
         ))
     }
 
+    /// Rewrites a `javascript:` URL after decoding it the way a browser does,
+    /// see `js::Rewriter::rewrite_javascript_url`. Gives back anything else
+    /// unchanged. `base` is what URLs in the script resolve against.
+    pub fn rewrite_javascript_url(&self, url: String, base: String) -> String {
+        let flags = Flags {
+            base,
+            invalid_js: self.invalid_js,
+            max_input_size: self.budget.max_input_size,
+            max_changes: self.budget.max_changes,
+            time_budget: self.budget.time,
+            max_steps: self.budget.max_steps,
+            over_budget: self.budget.policy,
+            ..Flags::default()
+        };
+        self.js.rewrite_javascript_url(&url, flags)
+    }
+
+    /// Ways the script may get around the rewrite, as `{ category,
+    /// description, start, end }` objects. Nothing is rewritten.
+    pub fn audit_js(&self, js: String, url: String, module: Option<bool>) -> Array {
+        let flags = Flags {
+            url,
+            is_module: module.into(),
+            ..Flags::default()
+        };
+        let audit = self.js.audit(&js, &flags);
+        let lines = LineIndex::new(&js);
+        let out = Array::new();
+        for finding in &audit.findings {
+            out.push(&jsr::finding_object(finding, &lines));
+        }
+        out
+    }
+
     #[wasm_bindgen(getter)]
     pub fn webrascal(&self) -> Object {
         self.webrascal.clone()
     }
 }
 
+/// Maps `error.stack` back to the scripts the page loaded, for
+/// `flags.cleanErrors`.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct StackRewriter {
+    maps: StackMaps,
+}
+
+#[wasm_bindgen]
+impl StackRewriter {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> StackRewriter {
+        Self::default()
+    }
+
+    /// Registers a rewritten script by its `rascaltag`, with its URL before
+    /// proxying, its output and its binary map. Gives false if the map is
+    /// malformed.
+    pub fn register(&mut self, tag: String, url: String, js: String, map: Vec<u8>) -> bool {
+        self.maps.register(&tag, &url, &js, &map)
+    }
+
+    /// Frames at `url` are in the script registered as `tag`.
+    pub fn alias(&mut self, url: String, tag: String) {
+        self.maps.alias(&url, &tag);
+    }
+
+    /// Frames at URLs starting with `prefix` are the runtime's and dropped.
+    pub fn add_runtime(&mut self, prefix: String) {
+        self.maps.add_runtime(&prefix);
+    }
+
+    pub fn rewrite(&self, stack: String) -> String {
+        self.maps.rewrite(&stack)
+    }
+}
+
+/// The source `js`, the part of a rewritten script at output offset `start`,
+/// was rewritten from, given the script's binary map. For
+/// `Function.prototype.toString`.
+#[wasm_bindgen]
+pub fn original_text(map: Vec<u8>, start: u32, js: String) -> Option<String> {
+    transform::original_text(&map, start, &js)
+}
+
+fn read_flag(webrascal: &Object, key: &str) -> Option<bool> {
+    let flags = Reflect::get(webrascal, &JsValue::from_str("flags")).ok()?;
+    Reflect::get(&flags, &JsValue::from_str(key))
+        .ok()?
+        .as_bool()
+}
+
+/// A field of the config object serialized back to JSON, for the parts the
+/// `js` crate loads with serde.
+fn json_field(webrascal: &Object, key: &str) -> Option<String> {
+    let value = Reflect::get(webrascal, &JsValue::from_str(key)).ok()?;
+    if value.is_undefined() || value.is_null() {
+        return None;
+    }
+    JSON::stringify(&value).ok()?.as_string()
+}
+
+fn budget_from_object(webrascal: &Object) -> Result<Budget, JsValue> {
+    let Ok(budget) = Reflect::get(webrascal, &JsValue::from_str("budget")) else {
+        return Ok(Budget::default());
+    };
+    if budget.is_undefined() || budget.is_null() {
+        return Ok(Budget::default());
+    }
+    let number = |key: &str| {
+        Reflect::get(&budget, &JsValue::from_str(key))
+            .ok()
+            .and_then(|v| v.as_f64())
+            .filter(|n| *n >= 0.0)
+    };
+    let policy = match Reflect::get(&budget, &JsValue::from_str("policy"))
+        .ok()
+        .and_then(|v| v.as_string())
+    {
+        Some(policy) => policy.parse().map_err(|e: String| JsValue::from_str(&e))?,
+        None => BudgetPolicy::default(),
+    };
+    Ok(Budget {
+        max_input_size: number("maxInputSize").map(|n| n as usize),
+        max_changes: number("maxChanges").map(|n| n as usize),
+        time: number("timeMs").map(|n| Duration::from_secs_f64(n / 1000.0)),
+        max_steps: number("maxSteps").map(|n| n as u64),
+        policy,
+    })
+}
+
 fn config_from_object(webrascal: &Object) -> Option<Config> {
     let globals = Reflect::get(webrascal, &JsValue::from_str("globals")).ok()?;
     let prefix = Reflect::get(webrascal, &JsValue::from_str("prefix"))
@@ -164,10 +397,18 @@ fn config_from_object(webrascal: &Object) -> Option<Config> {
     read_global!(templocid, "templocid");
     read_global!(tempunusedid, "tempunusedid");
 
-    Some(cfg)
-}
+    macro_rules! read_list {
+        ($field:ident, $key:literal) => {
+            if let Ok(v) = Reflect::get(webrascal, &JsValue::from_str($key)) {
+                if let Ok(list) = v.dyn_into::<Array>() {
+                    cfg.$field = list.iter().filter_map(|s| s.as_string()).collect();
+                }
+            }
+        };
+    }
 
-fn random_tag() -> String {
-    let now = js_sys::Date::now() as u64;
-    format!("tag-{now:x}")
+    read_list!(unsafe_globals, "unsafeGlobals");
+    read_list!(unsafe_properties, "unsafeProperties");
+
+    Some(cfg)
 }
@@ -0,0 +1,23 @@
+// Lets `node --test` load the sources as they are written for the bundler:
+// relative imports without an extension resolve to the `.ts` file, or to
+// `index.ts` for a directory.
+import { register } from "node:module";
+
+register("data:text/javascript," + encodeURIComponent(`
+  import { existsSync, statSync } from "node:fs";
+  import { fileURLToPath } from "node:url";
+
+  export async function resolve(specifier, context, next) {
+    if (/^\\.\\.?\\//.test(specifier) && !/\\.[cm]?[jt]s$/.test(specifier) && context.parentURL) {
+      const url = new URL(specifier, context.parentURL);
+      const path = fileURLToPath(url);
+      if (existsSync(path + ".ts")) {
+        return next(url.href + ".ts", context);
+      }
+      if (existsSync(path) && statSync(path).isDirectory()) {
+        return next(new URL("index.ts", url.href + "/").href, context);
+      }
+    }
+    return next(specifier, context);
+  }
+`));
@@ -3,8 +3,10 @@ import { config } from "../../shared";
 
 export default function hookSourceMaps(client: WebrascalClient): void {
   const pushFn = config.globals.pushsourcemapfn;
-  Reflect.set(globalThis, pushFn, (tag: string, map: Uint8Array) => {
-    client.box.sourcemaps.set(tag, map);
+  Reflect.set(globalThis, pushFn, (tag: string, map: string | Uint8Array) => {
+    // Rewritten scripts register their map inline as base64.
+    const bytes = typeof map === "string" ? Uint8Array.from(atob(map), (c) => c.charCodeAt(0)) : map;
+    client.box.sourcemaps.set(tag, bytes);
   });
 
   client.Proxy("Function.prototype.toString", {
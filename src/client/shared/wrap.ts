@@ -3,34 +3,38 @@ import { config, flagEnabled } from "../../shared";
 import { indirectEval } from "./eval";
 import { WEBRASCALCLIENT } from "../../symbols";
 
-const UNSAFE_GLOBALS = new Set(["location", "parent", "top", "eval"]);
-
 export function createWrapFn(client: WebrascalClient, selfRef: typeof globalThis): (identifier: unknown, strict: boolean) => unknown {
   const isWindow = typeof Window !== "undefined" && selfRef instanceof Window;
 
-  return function wrapFn(identifier: unknown, strict: boolean): unknown {
-    if (identifier === selfRef.location) {
-      return client.locationProxy;
-    }
-    if (identifier === selfRef.eval) {
-      return indirectEval.bind(client, strict);
-    }
-
-    if (isWindow) {
-      if (identifier === selfRef.parent) {
-        const parentRef = selfRef.parent as unknown as Record<symbol, unknown>;
-        return parentRef[WEBRASCALCLIENT] ? selfRef.parent : selfRef;
-      }
-      if (identifier === selfRef.top) {
-        let current = selfRef;
-        while (current.parent !== current) {
-          const parentRef = current.parent as unknown as Record<symbol, unknown>;
-          if (!parentRef[WEBRASCALCLIENT]) {
-            break;
-          }
-          current = current.parent;
+  // What each unsafe name is replaced with. Names the config lists without a
+  // wrapper here get their real value.
+  const wrappers: Record<string, (strict: boolean) => unknown> = {
+    location: () => client.locationProxy,
+    eval: (strict) => indirectEval.bind(client, strict)
+  };
+  if (isWindow) {
+    wrappers.parent = () => {
+      const parentRef = selfRef.parent as unknown as Record<symbol, unknown>;
+      return parentRef[WEBRASCALCLIENT] ? selfRef.parent : selfRef;
+    };
+    wrappers.top = () => {
+      let current = selfRef;
+      while (current.parent !== current) {
+        const parentRef = current.parent as unknown as Record<symbol, unknown>;
+        if (!parentRef[WEBRASCALCLIENT]) {
+          break;
         }
-        return current;
+        current = current.parent;
+      }
+      return current;
+    };
+  }
+  const wrapped = [...new Set([...config.unsafeGlobals, ...config.unsafeProperties])].filter((name) => Object.prototype.hasOwnProperty.call(wrappers, name));
+
+  return function wrapFn(identifier: unknown, strict: boolean): unknown {
+    for (const name of wrapped) {
+      if (identifier === Reflect.get(selfRef, name)) {
+        return wrappers[name](strict);
       }
     }
 
@@ -43,12 +47,13 @@ export function createWrapFn(client: WebrascalClient, selfRef: typeof globalThis
 
 export default function hookWrap(client: WebrascalClient, selfRef: typeof globalThis): void {
   const globals = config.globals;
+  const unsafeProperties = new Set(config.unsafeProperties);
   const wrapFn = createWrapFn(client, selfRef);
 
   Reflect.set(selfRef, globals.wrapfn, wrapFn);
 
   Reflect.set(selfRef, globals.wrappropertyfn, (prop: string) => {
-    if (UNSAFE_GLOBALS.has(prop)) {
+    if (unsafeProperties.has(prop)) {
       return `${globals.wrappropertybase}${prop}`;
     }
     return prop;
@@ -68,7 +73,7 @@ export default function hookWrap(client: WebrascalClient, selfRef: typeof global
 
   Reflect.set(selfRef, "$rascalitize", (value: unknown) => value);
 
-  for (const unsafeName of UNSAFE_GLOBALS) {
+  for (const unsafeName of unsafeProperties) {
     const trapName = `${globals.wrappropertybase}${unsafeName}`;
     if (Object.prototype.hasOwnProperty.call(Object.prototype, trapName)) {
       continue;
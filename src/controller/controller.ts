@@ -1,6 +1,6 @@
 import { WEBRASCALCONTROLLER } from "../symbols";
 import type { MessageW2C, WebrascalConfig, WebrascalInitConfig } from "../types";
-import { loadCodecs, setConfig } from "../shared";
+import { DEFAULT_UNSAFE_NAMES, loadCodecs, setConfig } from "../shared";
 import { WebrascalFrame } from "./frame";
 
 export class WebrascalGlobalDownloadEvent extends Event {
@@ -113,6 +113,8 @@ function mergeConfig(input: Partial<WebrascalInitConfig>): WebrascalConfig {
       allowFailedIntercepts: true
     },
     siteFlags: {},
+    unsafeGlobals: [...DEFAULT_UNSAFE_NAMES],
+    unsafeProperties: [...DEFAULT_UNSAFE_NAMES],
     codec: {
       encode: "(input) => btoa(input)",
       decode: "(input) => atob(input)"
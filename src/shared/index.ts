@@ -1,5 +1,10 @@
 import type { WebrascalConfig, WebrascalFlags } from "../types";
 
+// Names the rewriter wraps by default, as globals and as properties. The same
+// list as `DEFAULT_UNSAFE_GLOBALS` in rewriter/js/src/cfg.rs, which the
+// native rewriter defaults to.
+export const DEFAULT_UNSAFE_NAMES: readonly string[] = ["location", "parent", "top", "eval"];
+
 export let config: WebrascalConfig;
 export let codecEncode: (input: string) => string = (input) => input;
 export let codecDecode: (input: string) => string = (input) => input;
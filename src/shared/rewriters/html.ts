@@ -1,5 +1,5 @@
 import type { URLMeta } from "../../types";
-import { config } from "../index";
+import { DEFAULT_UNSAFE_NAMES, config } from "../index";
 import { rewriteJs } from "./js";
 import { rewriteCss } from "./css";
 import { rewriteUrl } from "./url";
@@ -15,7 +15,7 @@ export function rewriteHtml(input: string, meta: URLMeta, fromTop = false): stri
     if (/\bsrc\s*=/.test(attrs)) {
       return full;
     }
-    const rewritten = rewriteJs(body, meta.base.toString(), meta, /type=["']module["']/.test(attrs));
+    const rewritten = rewriteJs(body, meta.base.toString(), meta, scriptIsModule(attrs));
     return `<script${attrs}>${rewritten}</script>`;
   });
 
@@ -91,9 +91,26 @@ function fallbackConfig() {
       allowFailedIntercepts: true
     },
     siteFlags: {},
+    unsafeGlobals: [...DEFAULT_UNSAFE_NAMES],
+    unsafeProperties: [...DEFAULT_UNSAFE_NAMES],
     codec: {
       encode: "(input) => btoa(input)",
       decode: "(input) => atob(input)"
     }
   };
 }
+
+// From the type attribute of an inline <script>: a module, a classic script
+// when there is none or it is a JavaScript MIME type, and undefined for
+// anything else, which the rewriter tells from the syntax.
+function scriptIsModule(attrs: string): boolean | undefined {
+  const match = /\btype\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))/i.exec(attrs);
+  const type = (match?.[1] ?? match?.[2] ?? match?.[3] ?? "").trim().toLowerCase();
+  if (type === "module") {
+    return true;
+  }
+  if (type === "" || /^(?:text|application)\/(?:x-)?(?:javascript|ecmascript)$/.test(type)) {
+    return false;
+  }
+  return undefined;
+}
@@ -1,23 +1,107 @@
 import type { URLMeta } from "../../types";
-import { getRewriter } from "./wasm";
+import { flagEnabled } from "../index";
+import { getRewriter, type RewriterDiagnostic } from "./wasm";
 
-export function rewriteJs(input: string | Uint8Array, base: string, meta: URLMeta, module = false): string {
-  const source = typeof input === "string" ? input : new TextDecoder().decode(input);
-  let rewriter: { rewrite_js: (js: string, inBase: string, url: string, isModule: boolean) => { js: Uint8Array } };
+// A script the rewriter refused under the configured invalid-JS or budget
+// policy. Serving it as it came would let it run unproxied, so it goes up to
+// the caller instead of falling back like other rewriter failures.
+export type RewriteRejection = Error & (
+  | { kind: "invalid-js"; errors?: RewriterDiagnostic[] }
+  | { kind: "over-budget"; budget: "input-size" | "changes" | "time" | "steps" }
+);
+
+export function isRewriteRejection(err: unknown): err is RewriteRejection {
+  const kind = err instanceof Error ? (err as { kind?: unknown }).kind : undefined;
+  return kind === "invalid-js" || kind === "over-budget";
+}
+
+// `module` is left undefined for a script whose type isn't known, such as one
+// fetched with no `type=module` on its URL, to have the rewriter tell from its
+// syntax. `charset` is the label from the Content-Type or <script charset>,
+// used for byte input without a BOM. The result is always a string, so
+// whatever the input was in, it goes out as UTF-8.
+export function rewriteJs(input: string | Uint8Array, base: string, meta: URLMeta, module?: boolean, charset?: string): string {
+  return rewriteScript(input, base, meta, module, charset).js;
+}
+
+// `rewriteJs`, also giving whether the script was taken for a module, which
+// an undefined `module` leaves to the rewriter. A script that couldn't be
+// rewritten is taken for a classic one.
+export function rewriteScript(
+  input: string | Uint8Array,
+  base: string,
+  meta: URLMeta,
+  module?: boolean,
+  charset?: string
+): { js: string; module: boolean } {
+  const fallback = () => ({
+    js: typeof input === "string" ? input : decodeBytes(input, charset),
+    module: module ?? false
+  });
+  let rewriter: ReturnType<typeof getRewriter>[0];
   let release = () => {};
   try {
     [rewriter, release] = getRewriter(meta);
   } catch (err) {
     console.warn("[webrascal] failed to acquire wasm rewriter, using pass-through:", err);
-    return source;
+    return fallback();
   }
 
   try {
-    const out = rewriter.rewrite_js(source, base, base, module);
-    return new TextDecoder().decode(out.js);
-  } catch {
-    return source;
+    // Formatted output with the wrappers in context, for reading rewrites of
+    // minified code.
+    const logs = flagEnabled("rewriterLogs", meta.base);
+    rewriter.set_pretty(logs);
+    const out = typeof input === "string"
+      ? rewriter.rewrite_js(input, base, base, module)
+      : rewriter.rewrite_js_bytes(input, base, base, module, undefined, charset);
+    if (logs && out.pretty) {
+      console.debug(`[webrascal] rewrote ${base}:\n${out.pretty.code}`);
+    }
+    return { js: new TextDecoder().decode(out.js), module: out.module };
+  } catch (err) {
+    if (isRewriteRejection(err)) {
+      throw err;
+    }
+    return fallback();
   } finally {
     release();
   }
 }
+
+// Decoded first the way a browser decodes it, so percent-encoding can't hide
+// code from the rewrite. A URL the rewriter fails on does nothing.
+export function rewriteJavascriptUrl(url: string, meta: URLMeta): string {
+  let release = () => {};
+  try {
+    let rewriter: ReturnType<typeof getRewriter>[0];
+    [rewriter, release] = getRewriter(meta);
+    return rewriter.rewrite_javascript_url(url, meta.base.href);
+  } catch (err) {
+    console.warn("[webrascal] failed to rewrite javascript: URL:", err);
+    return "javascript:void%200";
+  } finally {
+    release();
+  }
+}
+
+// Whether a browser would run `url` as script: the scheme matched without
+// case, after leading controls and spaces and any tabs or newlines.
+export function isJavascriptUrl(url: string): boolean {
+  return url.replace(/[\t\n\r]/g, "").replace(/^[\x00-\x20]+/, "").slice(0, 11).toLowerCase() === "javascript:";
+}
+
+// For when the rewriter can't: by the label, or as UTF-8 without a usable one.
+function decodeBytes(input: Uint8Array, charset?: string): string {
+  try {
+    return new TextDecoder(charset || "utf-8").decode(input);
+  } catch {
+    return new TextDecoder().decode(input);
+  }
+}
+
+// The charset parameter of a Content-Type header, if it has one.
+export function charsetOf(contentType: string): string | undefined {
+  const match = /;\s*charset\s*=\s*"?([^";\s]+)/i.exec(contentType);
+  return match ? match[1] : undefined;
+}
@@ -1,6 +1,6 @@
 import { codecDecode, codecEncode, config } from "../index";
 import type { URLMeta } from "../../types";
-import { rewriteJs } from "./js";
+import { isJavascriptUrl, rewriteJavascriptUrl } from "./js";
 
 const PASSTHROUGH_PROTOCOLS = new Set(["mailto:", "about:", "tel:"]);
 const DEFAULT_PREFIX = "/webrascal/";
@@ -15,10 +15,8 @@ export function rewriteUrl(input: string | URL, meta: URLMeta): string {
     return raw;
   }
 
-  if (raw.startsWith("javascript:")) {
-    const payload = raw.slice("javascript:".length);
-    const rewrittenJs = rewriteJs(payload, "javascript-url", meta, false);
-    return `javascript:${rewrittenJs}`;
+  if (isJavascriptUrl(raw)) {
+    return rewriteJavascriptUrl(raw, meta);
   }
 
   if (raw.startsWith("blob:") || raw.startsWith("data:")) {
@@ -1,14 +1,74 @@
 import type { URLMeta } from "../../types";
 
+type DiagnosticPosition = { offset: number; line: number; column: number };
+
+type DiagnosticLabel = { start: DiagnosticPosition; end: DiagnosticPosition; message: string | null };
+
+export type RewriterDiagnostic = {
+  message: string;
+  severity: "error" | "warning" | "advice";
+  code: string;
+  url: string;
+  span: DiagnosticLabel | null;
+  labels: DiagnosticLabel[];
+  help: string | null;
+};
+
+type DiagnosticPositionRange = { start: DiagnosticPosition; end: DiagnosticPosition };
+
+export type RewriterStats = {
+  counts: Record<string, number>;
+  prefilter: number;
+  parse: number;
+  visit: number;
+  transform: number;
+  inputSize: number;
+  outputSize: number;
+};
+
+export type RewriterProvenance = DiagnosticPositionRange & { kind: string; source: string };
+
+export type EscapeFinding = DiagnosticPositionRange & {
+  category:
+    | "dynamic-global-property"
+    | "with"
+    | "dynamic-function"
+    | "document-domain"
+    | "callee-caller"
+    | "proto-walk"
+    | "runtime-name";
+  description: string;
+};
+
 type RewriterOutput = {
   js: Uint8Array;
   map: Uint8Array;
   rascaltag: string;
-  errors: string[];
+  errors: RewriterDiagnostic[];
+  module: boolean;
+  outcome: "rewritten" | "best-effort" | "passthrough" | "prefiltered" | "over-budget";
+  appliedRules: string[];
+  injectedScripts: string[];
+  stats: RewriterStats;
+  provenance: RewriterProvenance[];
+  sourceEncoding: string;
+  charset: "utf-8";
+  pretty: { code: string; map: string } | null;
 };
 
 type RewriterLike = {
-  rewrite_js: (js: string, base: string, url: string, module: boolean) => RewriterOutput;
+  rewrite_js: (js: string, base: string, url: string, module?: boolean) => RewriterOutput;
+  rewrite_js_bytes: (
+    js: Uint8Array,
+    base: string,
+    url: string,
+    module?: boolean,
+    salt?: string,
+    charset?: string
+  ) => RewriterOutput;
+  rewrite_javascript_url: (url: string, base: string) => string;
+  audit_js: (js: string, url: string, module?: boolean) => EscapeFinding[];
+  set_pretty: (enabled: boolean) => void;
 };
 
 type RewriterCtor = new (config: unknown) => RewriterLike;
@@ -17,13 +77,58 @@ const pool: Array<{ rewriter: RewriterLike; inUse: boolean }> = [];
 
 class PassThroughRewriter implements RewriterLike {
   rewrite_js(js: string): RewriterOutput {
+    const bytes = new TextEncoder().encode(js);
     return {
-      js: new TextEncoder().encode(js),
+      js: bytes,
       map: new Uint8Array(),
       rascaltag: "fallback",
-      errors: []
+      errors: [],
+      module: false,
+      outcome: "passthrough",
+      appliedRules: [],
+      injectedScripts: [],
+      stats: {
+        counts: {},
+        prefilter: 0,
+        parse: 0,
+        visit: 0,
+        transform: 0,
+        inputSize: bytes.length,
+        outputSize: bytes.length
+      },
+      provenance: [],
+      sourceEncoding: "UTF-8",
+      charset: "utf-8",
+      pretty: null
     };
   }
+
+  rewrite_js_bytes(
+    js: Uint8Array,
+    _base?: string,
+    _url?: string,
+    _module?: boolean,
+    _salt?: string,
+    charset?: string
+  ): RewriterOutput {
+    let decoder: TextDecoder;
+    try {
+      decoder = new TextDecoder(charset ?? "utf-8");
+    } catch {
+      decoder = new TextDecoder("utf-8");
+    }
+    return { ...this.rewrite_js(decoder.decode(js)), sourceEncoding: decoder.encoding };
+  }
+
+  rewrite_javascript_url(url: string): string {
+    return url;
+  }
+
+  audit_js(): EscapeFinding[] {
+    return [];
+  }
+
+  set_pretty(): void {}
 }
 
 function build(meta: URLMeta): RewriterLike {
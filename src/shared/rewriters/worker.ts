@@ -1,11 +1,12 @@
 import type { URLMeta } from "../../types";
-import { rewriteJs } from "./js";
+import { rewriteScript } from "./js";
 
-export function rewriteWorkers(input: Uint8Array, type: "worker" | "sharedworker", url: string, meta: URLMeta): Uint8Array {
-  const source = new TextDecoder().decode(input);
-  const bootstrap = type === "worker"
-    ? `importScripts("${self.location.origin}/dist/webrascal.all.js");self.$webrascalLoadClient().loadAndHook(self.__WEBRASCAL_CONFIG__);\n`
-    : `import "${self.location.origin}/dist/webrascal.all.js";self.$webrascalLoadClient().loadAndHook(self.__WEBRASCAL_CONFIG__);\n`;
-  const rewritten = rewriteJs(source, url, meta, false);
-  return new TextEncoder().encode(bootstrap + rewritten);
-}
\ No newline at end of file
+// Module workers can't call importScripts, and classic ones can't import.
+// `module` is undefined when the URL didn't say, and the rewriter decides.
+export function rewriteWorkers(input: Uint8Array, module: boolean | undefined, url: string, meta: URLMeta, charset?: string): Uint8Array {
+  const rewritten = rewriteScript(input, url, meta, module, charset);
+  const bootstrap = rewritten.module
+    ? `import "${self.location.origin}/dist/webrascal.all.js";self.$webrascalLoadClient().loadAndHook(self.__WEBRASCAL_CONFIG__);\n`
+    : `importScripts("${self.location.origin}/dist/webrascal.all.js");self.$webrascalLoadClient().loadAndHook(self.__WEBRASCAL_CONFIG__);\n`;
+  return new TextEncoder().encode(bootstrap + rewritten.js);
+}
@@ -13,6 +13,30 @@ export interface WebrascalFlags {
   allowFailedIntercepts: boolean;
 }
 
+export type PatchRule = {
+  name: string;
+  url?: string;
+  host?: string;
+  action: "replace" | "insert-before" | "insert-after" | "delete";
+  text?: string;
+} & ({ snippet: string } | { call: string } | { member: string } | { identifier: string });
+
+export type InitScript = {
+  name: string;
+  url?: string;
+  host?: string;
+  kind?: "any" | "script" | "module";
+  code: string;
+};
+
+export type RewriteBudget = {
+  maxInputSize?: number;
+  maxChanges?: number;
+  timeMs?: number;
+  maxSteps?: number;
+  policy?: "passthrough" | "reject";
+};
+
 export interface WebrascalConfig {
   prefix: string;
   globals: {
@@ -36,6 +60,11 @@ export interface WebrascalConfig {
   };
   flags: WebrascalFlags;
   siteFlags: Record<string, Partial<WebrascalFlags>>;
+  unsafeGlobals: string[];
+  unsafeProperties: string[];
+  patchRules?: PatchRule[];
+  initScripts?: InitScript[];
+  budget?: RewriteBudget;
   codec: {
     encode: string;
     decode: string;
@@ -1,5 +1,5 @@
 import type { URLMeta } from "../types";
-import { rewriteCss, rewriteHeaders, rewriteHtml, rewriteJs, rewriteWorkers, unrewriteUrl, rewriteUrl } from "../shared/rewriters";
+import { charsetOf, isRewriteRejection, rewriteCss, rewriteHeaders, rewriteHtml, rewriteJs, rewriteWorkers, unrewriteUrl, rewriteUrl } from "../shared/rewriters";
 import { cleanExpiredTrackers, getMostRestrictiveSite, initializeTracker, storeReferrerPolicy, updateTracker } from "../shared/security/forceReferrer";
 import type { WebrascalServiceWorker } from "./index";
 import { renderErrorPage, renderNetErrorPage, type NetErrorPageInput } from "./error";
@@ -171,6 +171,12 @@ export async function handleFetch(sw: WebrascalServiceWorker, event: FetchEvent)
     const destination = request.destination;
     const isNavigatingDocument = destination === "document" || destination === "iframe" || request.mode === "navigate";
     const isHtml = contentType.includes("text/html") || contentType.includes("application/xhtml+xml");
+    // Script URLs rewritten by the wasm rewriter say what loads them, which
+    // the request doesn't for worklets or for workers fetched some other way.
+    const scriptDestination = requestUrl.searchParams.get("dest") || destination;
+    // Only modules are marked, and a script URL rewritten some other way has
+    // no mark either, so without one the rewriter tells from the syntax.
+    const isModule = requestUrl.searchParams.get("type") === "module" ? true : undefined;
 
     mark("rewrite-body:read-upstream-buffer");
     let bodyBytes = new Uint8Array(await upstream.arrayBuffer());
@@ -179,17 +185,19 @@ export async function handleFetch(sw: WebrascalServiceWorker, event: FetchEvent)
       const html = new TextDecoder().decode(bodyBytes);
       mark("rewrite-body:html");
       bodyBytes = new TextEncoder().encode(rewriteHtml(html, meta, true));
-    } else if (destination === "script") {
+    } else if (scriptDestination === "script" || scriptDestination === "paintworklet" || scriptDestination === "audioworklet") {
       mark("rewrite-body:js");
-      bodyBytes = new TextEncoder().encode(rewriteJs(bodyBytes, realUrl, meta, requestUrl.searchParams.get("type") === "module"));
+      bodyBytes = new TextEncoder().encode(rewriteJs(bodyBytes, realUrl, meta, isModule, charsetOf(contentType)));
+      markUtf8(rewrittenHeaders, contentType, "text/javascript");
     } else if (destination === "style") {
       mark("rewrite-body:decode-css-text");
       const css = new TextDecoder().decode(bodyBytes);
       mark("rewrite-body:css");
       bodyBytes = new TextEncoder().encode(rewriteCss(css, meta));
-    } else if (destination === "worker" || destination === "sharedworker") {
+    } else if (scriptDestination === "worker" || scriptDestination === "sharedworker" || scriptDestination === "serviceworker") {
       mark("rewrite-body:worker");
-      bodyBytes = rewriteWorkers(bodyBytes, destination as "worker" | "sharedworker", realUrl, meta);
+      bodyBytes = rewriteWorkers(bodyBytes, isModule, realUrl, meta, charsetOf(contentType));
+      markUtf8(rewrittenHeaders, contentType, "text/javascript");
     }
 
     mark("cleanup");
@@ -206,6 +214,18 @@ export async function handleFetch(sw: WebrascalServiceWorker, event: FetchEvent)
       headers: rewrittenHeaders
     });
   } catch (err) {
+    if (isRewriteRejection(err)) {
+      return simpleErrorResponse(
+        502,
+        `The rewriter refused the script at ${resolvedRealUrl} instead of serving it unproxied: ${err.message}`,
+        err.kind === "over-budget" ? "WRK-REWRITE-3002" : "WRK-REWRITE-3001",
+        "Script Rejected by Rewriter",
+        request.destination,
+        request.mode,
+        stage,
+        traceId
+      );
+    }
     const message = err instanceof Error ? err.message : String(err);
     const fetchLike = /fetch failed|failed to fetch/i.test(message);
     if (fetchLike) {
@@ -242,6 +262,13 @@ export async function handleFetch(sw: WebrascalServiceWorker, event: FetchEvent)
   }
 }
 
+// Rewritten bodies are UTF-8 whatever the upstream was in, so the charset the
+// upstream declared has to go.
+function markUtf8(headers: Headers, contentType: string, fallback: string): void {
+  const mime = contentType.split(";")[0].trim() || fallback;
+  headers.set("content-type", `${mime}; charset=utf-8`);
+}
+
 function simpleErrorResponse(
   status: number,
   summary: string,
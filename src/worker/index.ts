@@ -1,6 +1,6 @@
 import type { MessageC2W, MessageW2C, WebrascalConfig } from "../types";
 import { CookieStore } from "../shared/cookie";
-import { loadCodecs, setConfig } from "../shared";
+import { DEFAULT_UNSAFE_NAMES, loadCodecs, setConfig } from "../shared";
 import { rewriteUrl, unrewriteUrl } from "../shared/rewriters/url";
 import { handleFetch } from "./fetch";
 import { FakeServiceWorker } from "./fakesw";
@@ -68,6 +68,8 @@ export class WebrascalServiceWorker extends EventTarget {
         allowFailedIntercepts: true
       },
       siteFlags: {},
+      unsafeGlobals: [...DEFAULT_UNSAFE_NAMES],
+      unsafeProperties: [...DEFAULT_UNSAFE_NAMES],
       codec: {
         encode: "(input) => btoa(input)",
         decode: "(input) => atob(input)"
@@ -0,0 +1,71 @@
+import { beforeEach, test } from "node:test";
+import assert from "node:assert/strict";
+import type { WebrascalConfig } from "../src/types";
+import { setConfig } from "../src/shared";
+import { isRewriteRejection, rewriteJs, rewriteScript } from "../src/shared/rewriters/js";
+
+// Stands in for the wasm rewriter: each rewrite throws `failure` if one is
+// set, and otherwise tags the script so a rewrite is told from a fallback.
+// Scripts of unknown kind are taken for modules.
+let failure: Error | undefined;
+let kinds: Array<boolean | undefined> = [];
+
+class FakeRewriter {
+  set_pretty(): void {}
+
+  rewrite_js(js: string, _base: string, _url: string, module?: boolean) {
+    if (failure) {
+      throw failure;
+    }
+    kinds.push(module);
+    return { js: new TextEncoder().encode(`/*rewritten*/${js}`), module: module ?? true, pretty: null };
+  }
+}
+
+const scope = globalThis as Record<string, unknown>;
+scope.self = globalThis;
+scope.WebrascalWasmRewriter = FakeRewriter;
+setConfig({ flags: {}, siteFlags: {} } as unknown as WebrascalConfig);
+
+const meta = { base: new URL("https://example.com/app.js") };
+
+// What the wasm rewriter throws for a script rejected by a policy.
+function rejection(kind: string, message: string): Error {
+  return Object.assign(new Error(message), { kind });
+}
+
+beforeEach(() => {
+  failure = undefined;
+  kinds = [];
+});
+
+test("rewrites through the wasm rewriter", () => {
+  assert.equal(rewriteJs("x", meta.base.href, meta), "/*rewritten*/x");
+});
+
+test("leaves the kind of a script to the rewriter when it isn't known", () => {
+  assert.deepEqual(rewriteScript("x", meta.base.href, meta), { js: "/*rewritten*/x", module: true });
+  assert.deepEqual(rewriteScript("x", meta.base.href, meta, false), { js: "/*rewritten*/x", module: false });
+  assert.deepEqual(kinds, [undefined, false]);
+});
+
+test("falls back to the source when the rewriter fails", () => {
+  failure = new Error("unreachable");
+  assert.equal(rewriteJs("x", meta.base.href, meta), "x");
+});
+
+test("passes invalid-JS rejections up instead of serving the source", () => {
+  failure = rejection("invalid-js", "invalid js: Unexpected token");
+  assert.throws(
+    () => rewriteJs("x(", meta.base.href, meta),
+    (err) => isRewriteRejection(err) && err.kind === "invalid-js"
+  );
+});
+
+test("passes budget rejections up instead of serving the source", () => {
+  failure = Object.assign(rejection("over-budget", "rewrite went over its changes budget"), { budget: "changes" });
+  assert.throws(
+    () => rewriteJs("x", meta.base.href, meta),
+    (err) => isRewriteRejection(err) && err.kind === "over-budget"
+  );
+});
@@ -0,0 +1,29 @@
+import { test } from "node:test";
+import assert from "node:assert/strict";
+import type { WebrascalConfig } from "../src/types";
+import { setConfig } from "../src/shared";
+import { rewriteUrl } from "../src/shared/rewriters/url";
+
+// Stands in for the wasm rewriter, recording the URLs it was handed.
+const seen: string[] = [];
+
+class FakeRewriter {
+  rewrite_javascript_url(url: string): string {
+    seen.push(url);
+    return "javascript:rewritten";
+  }
+}
+
+const scope = globalThis as Record<string, unknown>;
+scope.self = globalThis;
+scope.WebrascalWasmRewriter = FakeRewriter;
+setConfig({ prefix: "/webrascal/", flags: {}, siteFlags: {} } as unknown as WebrascalConfig);
+
+const meta = { base: new URL("https://example.com/") };
+
+test("hands javascript: URLs to the wasm rewriter whole", () => {
+  for (const url of ["javascript:alert(1)", " JavaScript:alert%281%29", "java\nscript:alert(1)"]) {
+    assert.equal(rewriteUrl(url, meta), "javascript:rewritten");
+  }
+  assert.deepEqual(seen, ["javascript:alert(1)", " JavaScript:alert%281%29", "java\nscript:alert(1)"]);
+});